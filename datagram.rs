@@ -0,0 +1,422 @@
+//! Unified datagram socket type for UDP and UNIX datagram sockets.
+
+use std::io;
+#[cfg(feature = "tcp")]
+use std::net;
+use std::time::Duration;
+#[cfg(all(unix, feature = "unix"))]
+use std::os::unix::net as unix;
+#[cfg(unix)]
+use std::os::unix::io::{AsFd,AsRawFd,BorrowedFd,IntoRawFd,RawFd};
+
+use crate::SocketAddr;
+#[cfg(all(unix, feature = "unix"))]
+use crate::PeerCred;
+
+
+#[cfg(all(unix, feature = "unix", feature = "tcp"))]
+fn family_mismatch() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "address family doesn't match the socket")
+}
+
+/// Wrapper for a `std::net::UdpSocket` or `UnixDatagram`.
+///
+/// Methods taking an address fail with an error of kind `InvalidInput` when the address belongs
+/// to a different family than the socket. The `Inet` variant requires the `tcp` feature, which
+/// covers UDP as well.
+#[derive(Debug)]
+pub enum Datagram {
+    #[cfg(feature = "tcp")]
+    Inet(net::UdpSocket),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(unix::UnixDatagram)
+}
+
+#[cfg(feature = "tcp")]
+impl From<net::UdpSocket> for Datagram {
+    fn from(s: net::UdpSocket) -> Datagram {
+        Datagram::Inet(s)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl From<unix::UnixDatagram> for Datagram {
+    fn from(s: unix::UnixDatagram) -> Datagram {
+        Datagram::Unix(s)
+    }
+}
+
+impl Datagram {
+    /// Creates a socket bound to the given address.
+    ///
+    /// Binding to `SocketAddr::UnnamedUnix` creates a UNIX datagram socket that isn't bound to any
+    /// address, which can still send datagrams with `send_to()` or after `connect()`.
+    pub fn bind(s: &SocketAddr) -> io::Result<Datagram> {
+        match s {
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(s) => net::UdpSocket::bind(s).map(Datagram::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(s) => unix::UnixDatagram::bind(s).map(Datagram::Unix),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::UnnamedUnix => unix::UnixDatagram::unbound().map(Datagram::Unix),
+            #[cfg(all(target_os = "linux", feature = "unix"))]
+            SocketAddr::AbstractUnix(n) => {
+                use std::os::linux::net::SocketAddrExt;
+                let a = unix::SocketAddr::from_abstract_name(n)?;
+                unix::UnixDatagram::bind_addr(&a).map(Datagram::Unix)
+            },
+            #[cfg(not(feature = "tcp"))]
+            SocketAddr::Inet(_) => Err(crate::tcp_disabled()),
+            #[cfg(all(unix, not(feature = "unix")))]
+            _ => Err(crate::unix_disabled())
+        }
+    }
+
+    /// Connects the socket to a remote address, so that `send()` and `recv()` can be used.
+    pub fn connect(&self, s: &SocketAddr) -> io::Result<()> {
+        match (self, s) {
+            #[cfg(feature = "tcp")]
+            (Datagram::Inet(d), SocketAddr::Inet(s)) => d.connect(s),
+            #[cfg(all(unix, feature = "unix"))]
+            (Datagram::Unix(d), SocketAddr::Unix(s)) => d.connect(s),
+            #[cfg(all(target_os = "linux", feature = "unix"))]
+            (Datagram::Unix(d), SocketAddr::AbstractUnix(n)) => {
+                use std::os::linux::net::SocketAddrExt;
+                d.connect_addr(&unix::SocketAddr::from_abstract_name(n)?)
+            },
+            #[cfg(all(unix, feature = "unix"))]
+            (Datagram::Unix(_), SocketAddr::UnnamedUnix) => Err(crate::unnamed_addr()),
+            #[cfg(not(feature = "tcp"))]
+            (_, SocketAddr::Inet(_)) => Err(crate::tcp_disabled()),
+            #[cfg(all(unix, not(feature = "unix")))]
+            _ => Err(crate::unix_disabled()),
+            #[cfg(all(unix, feature = "unix", feature = "tcp"))]
+            _ => Err(family_mismatch())
+        }
+    }
+
+    pub fn send_to(&self, buf: &[u8], s: &SocketAddr) -> io::Result<usize> {
+        match (self, s) {
+            #[cfg(feature = "tcp")]
+            (Datagram::Inet(d), SocketAddr::Inet(s)) => d.send_to(buf, s),
+            #[cfg(all(unix, feature = "unix"))]
+            (Datagram::Unix(d), SocketAddr::Unix(s)) => d.send_to(buf, s),
+            #[cfg(all(target_os = "linux", feature = "unix"))]
+            (Datagram::Unix(d), SocketAddr::AbstractUnix(n)) => {
+                use std::os::linux::net::SocketAddrExt;
+                d.send_to_addr(buf, &unix::SocketAddr::from_abstract_name(n)?)
+            },
+            #[cfg(all(unix, feature = "unix"))]
+            (Datagram::Unix(_), SocketAddr::UnnamedUnix) => Err(crate::unnamed_addr()),
+            #[cfg(not(feature = "tcp"))]
+            (_, SocketAddr::Inet(_)) => Err(crate::tcp_disabled()),
+            #[cfg(all(unix, not(feature = "unix")))]
+            _ => Err(crate::unix_disabled()),
+            #[cfg(all(unix, feature = "unix", feature = "tcp"))]
+            _ => Err(family_mismatch())
+        }
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize,SocketAddr)> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.recv_from(buf).map(|(n,e)| (n, e.into())),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.recv_from(buf).map(|(n,e)| (n, e.into()))
+        }
+    }
+
+    /// Sets `SO_PASSCRED`, making the kernel attach the credentials of the sender to incoming
+    /// datagrams so that `recv_with_cred()` can report them.
+    ///
+    /// Only supported for UNIX datagram sockets on Linux; elsewhere an error of kind
+    /// `Unsupported` is returned.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn set_passcred(&self, passcred: bool) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(_) => Err(crate::unsupported("Credential passing")),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Datagram::Unix(d) => crate::sys::setsockopt(d.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PASSCRED,
+                                                        passcred as libc::c_int),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Datagram::Unix(_) => { let _ = passcred; Err(crate::unsupported("Credential passing")) }
+        }
+    }
+
+    /// Receives a datagram along with its source address and the credentials of the sending
+    /// process.
+    ///
+    /// `set_passcred(true)` must be called first, otherwise this fails with an error of kind
+    /// `InvalidData` as no credentials are attached. Datagrams that were already queued when it
+    /// was called carry no real credentials: their pid is reported as `None`, and their uid and
+    /// gid are the kernel's overflow ids (65534 by default, see `/proc/sys/kernel/overflowuid`).
+    /// Only supported for UNIX datagram sockets on Linux; elsewhere an error of kind
+    /// `Unsupported` is returned.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn recv_with_cred(&self, buf: &mut [u8]) -> io::Result<(usize,SocketAddr,PeerCred)> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(_) => Err(crate::unsupported("Credential passing")),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Datagram::Unix(d) => crate::sys::recv_with_cred(d.as_raw_fd(), buf).map(|(n, addr, (uid, gid, pid))| {
+                // The kernel reports pid 0 if the sender's credentials weren't recorded.
+                (n, addr, PeerCred::new(uid, gid, Some(pid).filter(|&p| p != 0)))
+            }),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Datagram::Unix(_) => { let _ = buf; Err(crate::unsupported("Credential passing")) }
+        }
+    }
+
+    /// Sends data to the connected peer.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.send(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.send(buf)
+        }
+    }
+
+    /// Receives data from the connected peer.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.recv(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.recv(buf)
+        }
+    }
+
+    /// Returns the full size of the next datagram without removing it from the queue, blocking
+    /// until one arrives, so that a buffer of the right size can be passed to `recv()`.
+    ///
+    /// Uses `MSG_PEEK | MSG_TRUNC`, which only reports the full size on Linux; elsewhere an error
+    /// of kind `Unsupported` is returned.
+    pub fn peek_len(&self) -> io::Result<usize> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            crate::sys::recv(self.as_raw_fd(), &mut [], libc::MSG_PEEK | libc::MSG_TRUNC)
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            Err(crate::unsupported("Peeking at the datagram size"))
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.local_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.local_addr().map(|e| e.into())
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.peer_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.peer_addr().map(|e| e.into())
+        }
+    }
+
+    /// Same as `Stream::set_read_timeout()`, including the rejection of zero durations.
+    pub fn set_read_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+        crate::check_timeout(t)?;
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.set_read_timeout(t),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.set_read_timeout(t)
+        }
+    }
+
+    /// Same as `set_read_timeout()`, for writes.
+    pub fn set_write_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+        crate::check_timeout(t)?;
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.set_write_timeout(t),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.set_write_timeout(t)
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.set_nonblocking(nonblocking),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.set_nonblocking(nonblocking)
+        }
+    }
+
+    /// Gets the value of the `SO_ERROR` option on this socket, clearing it in the process.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.take_error(),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.take_error()
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Datagram> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.try_clone().map(Datagram::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Datagram::Unix(d) => d.try_clone().map(Datagram::Unix)
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Datagram {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.as_raw_fd(),
+            #[cfg(feature = "unix")]
+            Datagram::Unix(d) => d.as_raw_fd()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for Datagram {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.as_fd(),
+            #[cfg(feature = "unix")]
+            Datagram::Unix(d) => d.as_fd()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl IntoRawFd for Datagram {
+    fn into_raw_fd(self) -> RawFd {
+        match self {
+            #[cfg(feature = "tcp")]
+            Datagram::Inet(d) => d.into_raw_fd(),
+            #[cfg(feature = "unix")]
+            Datagram::Unix(d) => d.into_raw_fd()
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+fn echo(server: Datagram, client: Datagram) {
+    let server_addr = server.local_addr().unwrap();
+    let t = std::thread::spawn(move || {
+        let mut buf = [0; 16];
+        let (n, peer) = server.recv_from(&mut buf).unwrap();
+        server.send_to(&buf[..n], &peer).unwrap();
+        server
+    });
+    assert_eq!(client.send_to(b"echo", &server_addr).unwrap(), 4);
+    let mut buf = [0; 16];
+    let (n, peer) = client.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"echo");
+    assert_eq!(peer, server_addr);
+    let server = t.join().unwrap();
+
+    // Connected mode
+    client.connect(&server_addr).unwrap();
+    assert_eq!(client.peer_addr().unwrap(), server_addr);
+    assert_eq!(client.send(b"x").unwrap(), 1);
+    assert_eq!(server.recv(&mut buf).unwrap(), 1);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_datagram_udp_echo() {
+    let server = Datagram::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let client = Datagram::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    echo(server, client);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_datagram_unix_echo() {
+    let server_path = crate::temp_sock_path("dgram-server");
+    let client_path = crate::temp_sock_path("dgram-client");
+    let server = Datagram::bind(&SocketAddr::Unix(server_path.clone())).unwrap();
+    let client = Datagram::bind(&SocketAddr::Unix(client_path.clone())).unwrap();
+    echo(server, client);
+
+    let unbound = Datagram::bind(&SocketAddr::UnnamedUnix).unwrap();
+    assert_eq!(unbound.local_addr().unwrap(), SocketAddr::UnnamedUnix);
+    #[cfg(feature = "tcp")]
+    assert_eq!(unbound.send_to(b"x", &SocketAddr::inet(([127, 0, 0, 1], 9))).unwrap_err().kind(),
+               io::ErrorKind::InvalidInput);
+    std::fs::remove_file(server_path).unwrap();
+    std::fs::remove_file(client_path).unwrap();
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "unix"))]
+fn test_datagram_recv_with_cred() {
+    let server_path = crate::temp_sock_path("dgram-cred-server");
+    let client_path = crate::temp_sock_path("dgram-cred-client");
+    let server = Datagram::bind(&SocketAddr::Unix(server_path.clone())).unwrap();
+    let client = Datagram::bind(&SocketAddr::Unix(client_path.clone())).unwrap();
+    let mut buf = [0; 16];
+
+    // Without SO_PASSCRED there are no credentials, and datagrams queued before it was enabled
+    // don't carry real ones.
+    client.send_to(b"early", &server.local_addr().unwrap()).unwrap();
+    assert_eq!(server.recv_with_cred(&mut buf).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    client.send_to(b"early", &server.local_addr().unwrap()).unwrap();
+    server.set_passcred(true).unwrap();
+    let (n, _, cred) = server.recv_with_cred(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"early");
+    assert_eq!(cred.pid(), None);
+    if let Ok(overflow) = std::fs::read_to_string("/proc/sys/kernel/overflowuid") {
+        assert_eq!(cred.uid(), overflow.trim().parse::<u32>().unwrap());
+    }
+
+    client.send_to(b"cred", &server.local_addr().unwrap()).unwrap();
+    let (n, from, cred) = server.recv_with_cred(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"cred");
+    assert_eq!(from, SocketAddr::Unix(client_path.clone()));
+    assert_eq!(cred.pid(), Some(std::process::id() as i32));
+    assert_eq!(cred.uid(), unsafe { libc::getuid() });
+    assert_eq!(cred.gid(), unsafe { libc::getgid() });
+
+    #[cfg(feature = "tcp")]
+    {
+        let udp = Datagram::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+        assert_eq!(udp.recv_with_cred(&mut buf).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert_eq!(udp.set_passcred(true).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+    std::fs::remove_file(server_path).unwrap();
+    std::fs::remove_file(client_path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(target_os = "linux", feature = "unix"))]
+fn test_datagram_peek_len() {
+    let server = Datagram::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let client = Datagram::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let msg = vec![7; 3000];
+    client.send_to(&msg, &server.local_addr().unwrap()).unwrap();
+    assert_eq!(server.peek_len().unwrap(), 3000);
+    // Peeking leaves the datagram in place, so it can be received in full afterwards.
+    let mut buf = vec![0; server.peek_len().unwrap()];
+    assert_eq!(server.recv(&mut buf).unwrap(), 3000);
+    assert_eq!(buf, msg);
+
+    let (a, b) = unix::UnixDatagram::pair().unwrap();
+    let (a, b) = (Datagram::from(a), Datagram::from(b));
+    a.send(&msg[..100]).unwrap();
+    assert_eq!(b.peek_len().unwrap(), 100);
+}
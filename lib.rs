@@ -1,5 +1,5 @@
-//! This crate provides unified `SocketAddr`, `Stream` and `Listener` types that work with both TCP
-//! and UNIX sockets.
+//! This crate provides unified `SocketAddr`, `Stream`, `Listener` and `Datagram` types that work
+//! with both TCP/UDP and UNIX sockets.
 //!
 //! Many applications don't really care whether they are connecting to a UNIX or TCP service, they
 //! simply want to use the service. Similarly, applications may want to provide a service over
@@ -11,31 +11,140 @@
 //! run-time configuration.
 //!
 //! These types should behave the same as the `SocketAddr`, `TcpStream`/`UnixStream` and
-//! `TcpListener`/`UnixListener` in libstd. There is currently no support for mio or tokio.
+//! `TcpListener`/`UnixListener` in libstd. Enabling the `tokio` feature adds `AsyncStream` and
+//! `AsyncListener`, which do the same for tokio's socket types, and the `mio` feature adds
+//! `MioStream` and `MioListener` for use in a mio event loop.
 //!
-//! UDP and Datagram sockets are not currently supported.
+//! The `Datagram` type does the same for `UdpSocket`/`UnixDatagram`, and `pool::ConnPool` keeps idle
+//! `Stream`s around for reuse.
 //!
-//! On Windows, these types only support TCP and are just lightweight wrappers around TCP sockets.
+//! On Windows, these types only support TCP and UDP and are just lightweight wrappers around the
+//! inet sockets. Windows 10 and later do support `AF_UNIX` stream sockets, but libstd has no types
+//! for them, and this crate won't implement them itself. Named pipes aren't supported either: they
+//! are file handles rather than sockets and can't provide the socket API. Use a crate such as
+//! `interprocess` for local sockets on Windows.
+//!
+//! Enabling the `serde` feature implements `Serialize` and `Deserialize` for `SocketAddr`, using
+//! the same string representation as `Display` and `FromStr`.
+//!
+//! The string representation itself (`Display`, `FromStr`, `SocketAddr::from_url()` and
+//! `Stream::connect_str()`) is behind the default `parsing` feature, which can be disabled to
+//! leave out the parsing code.
+//!
+//! Both socket families are behind default features as well: `tcp` covers TCP and UDP, and `unix`
+//! covers UNIX sockets. Disabling one of them removes its enum variants and code paths, and
+//! addresses of that family then fail with an error of kind `Unsupported`. At least one of them
+//! must be enabled.
+//!
+//! Enabling the `tracing` feature instruments `Stream::connect()`, `Listener::bind()` and
+//! `Listener::accept()` with `tracing` spans recording the address and its family.
+//!
+//! Enabling the `metrics` feature adds `CountedStream`, a `Stream` wrapper that is counted by
+//! `counted_stream_count()` while it exists. Plain `Stream`s aren't counted.
 
 use std::io;
 use std::net;
 use std::fmt;
-use std::time::Duration;
+use std::time::{Duration,Instant};
+#[cfg(feature = "parsing")]
 use std::str::FromStr;
 #[cfg(unix)]
 use std::path::{Path,PathBuf};
 #[cfg(unix)]
 use std::os::unix::net as unix;
+#[cfg(unix)]
+use std::os::unix::io::{AsFd,AsRawFd,BorrowedFd,FromRawFd,IntoRawFd,RawFd};
+
+
+mod datagram;
+pub mod pool;
+#[cfg(feature = "tcp")]
+mod socks5;
+#[cfg(unix)]
+mod sys;
+#[cfg(feature = "tokio")]
+mod tokio_net;
+#[cfg(feature = "mio")]
+mod mio_net;
+#[cfg(feature = "metrics")]
+mod metrics;
+
+pub use datagram::Datagram;
+#[cfg(feature = "tokio")]
+pub use tokio_net::{AsyncStream,AsyncListener};
+#[cfg(feature = "mio")]
+pub use mio_net::{MioStream,MioListener};
+#[cfg(feature = "metrics")]
+pub use metrics::{CountedStream,counted_stream_count};
+
+
+#[cfg(not(any(feature = "tcp", all(unix, feature = "unix"))))]
+compile_error!("at least one of the `tcp` and `unix` features must be enabled (`unix` only works on UNIX-like platforms)");
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} is not supported for this socket", what))
+}
+
+/// Returned for inet addresses when the `tcp` feature is disabled.
+#[cfg(not(feature = "tcp"))]
+fn tcp_disabled() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "TCP and UDP sockets are disabled, enable the `tcp` feature")
+}
 
+/// Returned for UNIX addresses when the `unix` feature is disabled.
+#[cfg(all(unix, not(feature = "unix")))]
+fn unix_disabled() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "UNIX sockets are disabled, enable the `unix` feature")
+}
+
+/// Rejects a zero timeout with a clearer error than the one libstd returns for it.
+fn check_timeout(t: Option<Duration>) -> io::Result<()> {
+    match t {
+        Some(t) if t == Duration::ZERO => Err(io::Error::new(io::ErrorKind::InvalidInput,
+            "timeout must be nonzero, pass None to disable it")),
+        _ => Ok(())
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+fn unnamed_addr() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "an unnamed UNIX socket address can't be connected or bound to")
+}
+
+/// Strips redundant trailing slashes from a UNIX socket path, leaving the root directory alone.
+#[cfg(unix)]
+fn trim_trailing_slashes(p: &Path) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    let mut bytes = p.as_os_str().as_bytes();
+    while bytes.len() > 1 && bytes.ends_with(b"/") {
+        bytes = &bytes[..bytes.len() - 1];
+    }
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
 
 /// Wrapper for a `std::net::SocketAddr` or UNIX socket path.
 ///
-/// UNIX sockets are prefixed with 'unix:' when parsing and formatting.
-#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+/// UNIX sockets are prefixed with 'unix:' when parsing and formatting. On Linux, a name starting
+/// with '@' (as in 'unix:@name') refers to a socket in the abstract namespace. Unnamed UNIX
+/// sockets, such as a client that connected without binding, are formatted as 'unix:<unnamed>'.
+/// Redundant trailing slashes are stripped from UNIX paths when parsing, so 'unix:/a.sock/' and
+/// 'unix:/a.sock' are the same address. IPv6 scope ids survive a round trip; on UNIX-like
+/// platforms an interface name such as '[fe80::1%eth0]:80' is accepted too, and resolved to
+/// its index.
+///
+/// Addresses are ordered by variant first, in declaration order (so all `Inet` addresses come
+/// before any `Unix` one), and by the inner value within a variant.
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub enum SocketAddr {
     Inet(net::SocketAddr),
     #[cfg(unix)]
-    Unix(PathBuf)
+    Unix(PathBuf),
+    /// A UNIX socket that is not bound to any address.
+    #[cfg(unix)]
+    UnnamedUnix,
+    /// A Linux abstract namespace UNIX socket, without the leading NUL byte.
+    #[cfg(target_os = "linux")]
+    AbstractUnix(Vec<u8>)
 }
 
 impl From<net::SocketAddr> for SocketAddr {
@@ -47,310 +156,4855 @@ impl From<net::SocketAddr> for SocketAddr {
 #[cfg(unix)]
 impl From<unix::SocketAddr> for SocketAddr {
     fn from(s: unix::SocketAddr) -> SocketAddr {
-        SocketAddr::Unix(match s.as_pathname() {
-            None => Path::new("unnamed").to_path_buf(),
-            Some(p) => p.to_path_buf()
-        })
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            if let Some(n) = s.as_abstract_name() {
+                return SocketAddr::AbstractUnix(n.to_vec());
+            }
+        }
+        match s.as_pathname() {
+            None => SocketAddr::UnnamedUnix,
+            Some(p) => SocketAddr::Unix(p.to_path_buf())
+        }
     }
 }
 
+/// Yields the inner address of `Inet` addresses, so a `SocketAddr` can be passed to functions
+/// that resolve addresses. UNIX socket addresses fail with an error of kind `InvalidInput`.
+impl net::ToSocketAddrs for SocketAddr {
+    type Iter = std::option::IntoIter<net::SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        match self {
+            SocketAddr::Inet(a) => Ok(Some(*a).into_iter()),
+            #[cfg(unix)]
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "a UNIX socket address is not an inet address"))
+        }
+    }
+}
+
+#[cfg(feature = "parsing")]
 impl fmt::Display for SocketAddr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             SocketAddr::Inet(n) => write!(f, "{}", n),
             #[cfg(unix)]
-            SocketAddr::Unix(n) => write!(f, "unix:{}", n.to_string_lossy())
+            SocketAddr::Unix(n) => write!(f, "unix:{}", n.to_string_lossy()),
+            #[cfg(unix)]
+            SocketAddr::UnnamedUnix => write!(f, "unix:<unnamed>"),
+            #[cfg(target_os = "linux")]
+            SocketAddr::AbstractUnix(n) => write!(f, "unix:@{}", String::from_utf8_lossy(n))
+        }
+    }
+}
+
+/// Returned by `SocketAddr::display_raw()`.
+#[cfg(feature = "parsing")]
+struct RawDisplay<'a>(&'a SocketAddr);
+
+#[cfg(feature = "parsing")]
+impl fmt::Display for RawDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            SocketAddr::Inet(n) => write!(f, "{}", n),
+            #[cfg(unix)]
+            SocketAddr::Unix(n) => write!(f, "{}", n.to_string_lossy()),
+            #[cfg(unix)]
+            SocketAddr::UnnamedUnix => Ok(()),
+            #[cfg(target_os = "linux")]
+            SocketAddr::AbstractUnix(n) => write!(f, "@{}", String::from_utf8_lossy(n))
         }
     }
 }
 
+#[cfg(feature = "parsing")]
 impl FromStr for SocketAddr {
-    type Err = net::AddrParseError;
+    type Err = ParseSocketAddrError;
 
     #[cfg(unix)]
-    fn from_str(s: &str) -> Result<SocketAddr, net::AddrParseError> {
-        if s.starts_with("unix:") {
-            Ok(SocketAddr::Unix(Path::new(s.trim_start_matches("unix:")).to_path_buf()))
+    fn from_str(s: &str) -> Result<SocketAddr, ParseSocketAddrError> {
+        if let Some(s) = s.strip_prefix("unix:") {
+            if s.is_empty() || s.contains('\0') {
+                return Err(ParseSocketAddrError::InvalidUnixPath);
+            }
+            if s == "<unnamed>" {
+                return Ok(SocketAddr::UnnamedUnix);
+            }
+            #[cfg(target_os = "linux")]
+            {
+                if let Some(name) = s.strip_prefix('@') {
+                    if name.is_empty() {
+                        return Err(ParseSocketAddrError::EmptyAbstractName);
+                    }
+                    return Ok(SocketAddr::AbstractUnix(name.as_bytes().to_vec()));
+                }
+            }
+            Ok(SocketAddr::Unix(trim_trailing_slashes(Path::new(s))))
         } else {
-            s.parse().map(SocketAddr::Inet)
+            s.parse().or_else(|e| parse_named_scope(s).ok_or(e))
+                .map(SocketAddr::Inet).map_err(ParseSocketAddrError::Inet)
         }
     }
 
     #[cfg(not(unix))]
-    fn from_str(s: &str) -> Result<SocketAddr, net::AddrParseError> {
-        s.parse().map(SocketAddr::Inet)
+    fn from_str(s: &str) -> Result<SocketAddr, ParseSocketAddrError> {
+        s.parse().map(SocketAddr::Inet).map_err(ParseSocketAddrError::Inet)
+    }
+}
+
+/// Parses an IPv6 address with a scope given as an interface name, such as '[fe80::1%eth0]:80',
+/// which libstd only accepts with a numeric scope id. `Display` prints the numeric id.
+#[cfg(all(feature = "parsing", unix))]
+fn parse_named_scope(s: &str) -> Option<net::SocketAddr> {
+    let (host, port) = s.strip_prefix('[')?.rsplit_once("]:")?;
+    let (ip, name) = host.split_once('%')?;
+    let scope_id = sys::if_nametoindex(name)?;
+    Some(net::SocketAddrV6::new(ip.parse().ok()?, port.parse().ok()?, 0, scope_id).into())
+}
+
+#[cfg(feature = "parsing")]
+impl std::convert::TryFrom<&str> for SocketAddr {
+    type Error = ParseSocketAddrError;
+
+    fn try_from(s: &str) -> Result<SocketAddr, ParseSocketAddrError> {
+        s.parse()
     }
 }
 
+#[cfg(feature = "parsing")]
+impl std::convert::TryFrom<String> for SocketAddr {
+    type Error = ParseSocketAddrError;
 
-impl SocketAddr {
-    pub fn is_unix(&self) -> bool {
+    fn try_from(s: String) -> Result<SocketAddr, ParseSocketAddrError> {
+        s.parse()
+    }
+}
+
+/// An error returned when parsing a `SocketAddr` fails.
+#[cfg(feature = "parsing")]
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[non_exhaustive]
+pub enum ParseSocketAddrError {
+    /// The address has no 'unix:' prefix and isn't a valid inet address either.
+    Inet(net::AddrParseError),
+    /// The path following the 'unix:' prefix is empty or contains a NUL byte.
+    InvalidUnixPath,
+    /// The name of an abstract UNIX socket ('unix:@') is empty.
+    EmptyAbstractName,
+    /// The URL passed to `SocketAddr::from_url()` has a scheme other than 'tcp' or 'unix'.
+    UnknownScheme,
+    /// An element of the list passed to `SocketAddr::parse_many()` failed to parse; `index`
+    /// counts from 0.
+    InList { index: usize, error: Box<ParseSocketAddrError> }
+}
+
+#[cfg(feature = "parsing")]
+impl fmt::Display for ParseSocketAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            #[cfg(unix)]
-            SocketAddr::Unix(_) => true,
-            _ => false,
+            ParseSocketAddrError::Inet(e) => write!(f, "{}", e),
+            ParseSocketAddrError::InvalidUnixPath => write!(f, "invalid UNIX socket path"),
+            ParseSocketAddrError::EmptyAbstractName => write!(f, "empty abstract UNIX socket name"),
+            ParseSocketAddrError::UnknownScheme => write!(f, "unknown URL scheme, expected 'tcp://' or 'unix:'"),
+            ParseSocketAddrError::InList { index, error } => write!(f, "address #{} in list: {}", index, error)
         }
     }
 }
 
+#[cfg(feature = "parsing")]
+impl std::error::Error for ParseSocketAddrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseSocketAddrError::Inet(e) => Some(e),
+            ParseSocketAddrError::InList { error, .. } => Some(error),
+            _ => None
+        }
+    }
+}
 
+#[cfg(feature = "parsing")]
+impl From<net::AddrParseError> for ParseSocketAddrError {
+    fn from(e: net::AddrParseError) -> ParseSocketAddrError {
+        ParseSocketAddrError::Inet(e)
+    }
+}
 
-
-#[derive(Debug)]
-pub enum Stream {
-    Inet(net::TcpStream),
-    #[cfg(unix)]
-    Unix(unix::UnixStream)
+#[cfg(feature = "serde")]
+impl serde::Serialize for SocketAddr {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
 }
 
-impl From<net::TcpStream> for Stream {
-    fn from(s: net::TcpStream) -> Stream {
-        Stream::Inet(s)
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SocketAddr {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<SocketAddr, D::Error> {
+        String::deserialize(d)?.parse().map_err(serde::de::Error::custom)
     }
 }
 
-#[cfg(unix)]
-impl From<unix::UnixStream> for Stream {
-    fn from(s: unix::UnixStream) -> Stream {
-        Stream::Unix(s)
+/// The address family of a `SocketAddr`, see `SocketAddr::family()`.
+///
+/// Displays as `ipv4`, `ipv6` or `unix`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum Family {
+    V4,
+    V6,
+    Unix
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Family::V4 => "ipv4",
+            Family::V6 => "ipv6",
+            Family::Unix => "unix"
+        })
     }
 }
 
-impl Stream {
-    pub fn connect(s: &SocketAddr) -> io::Result<Stream> {
-        match s {
-            SocketAddr::Inet(s) => net::TcpStream::connect(s).map(Stream::Inet),
-            #[cfg(unix)]
-            SocketAddr::Unix(s) => unix::UnixStream::connect(s).map(Stream::Unix)
+
+impl SocketAddr {
+    /// Creates an inet address.
+    pub fn inet<A: Into<net::SocketAddr>>(addr: A) -> SocketAddr {
+        SocketAddr::Inet(addr.into())
+    }
+
+    /// Creates an address for `127.0.0.1` and the given port.
+    pub fn loopback_v4(port: u16) -> SocketAddr {
+        SocketAddr::inet((net::Ipv4Addr::LOCALHOST, port))
+    }
+
+    /// Creates an address for `::1` and the given port.
+    pub fn loopback_v6(port: u16) -> SocketAddr {
+        SocketAddr::inet((net::Ipv6Addr::LOCALHOST, port))
+    }
+
+    /// Creates an address for `0.0.0.0` and the given port, to listen on all IPv4 interfaces.
+    pub fn unspecified_v4(port: u16) -> SocketAddr {
+        SocketAddr::inet((net::Ipv4Addr::UNSPECIFIED, port))
+    }
+
+    /// Creates an address for `::` and the given port, to listen on all IPv6 interfaces.
+    pub fn unspecified_v6(port: u16) -> SocketAddr {
+        SocketAddr::inet((net::Ipv6Addr::UNSPECIFIED, port))
+    }
+
+    /// Creates an inet address by resolving `host` (a host name or an IP literal) and attaching
+    /// `port` to the first address it resolves to.
+    ///
+    /// This may block on a DNS lookup. It never produces a UNIX address; use `unix()` for those.
+    pub fn inet_from_host(host: &str, port: u16) -> io::Result<SocketAddr> {
+        net::ToSocketAddrs::to_socket_addrs(&(host, port))?.next().map(SocketAddr::Inet).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")
+        })
+    }
+
+    /// Creates a UNIX socket address for the given path.
+    ///
+    /// Like any other path, a relative one is resolved against the current directory of the
+    /// process at the time of the `bind()` or `connect()` call, not when the address is created.
+    /// Trailing slashes are removed, as they are when parsing.
+    #[cfg(unix)]
+    pub fn unix<P: AsRef<Path>>(path: P) -> SocketAddr {
+        SocketAddr::Unix(trim_trailing_slashes(path.as_ref()))
+    }
+
+    /// Creates a UNIX socket address for the given path, failing with an error of kind
+    /// `InvalidInput` if the path is relative.
+    #[cfg(unix)]
+    pub fn unix_absolute<P: AsRef<Path>>(path: P) -> io::Result<SocketAddr> {
+        if path.as_ref().is_relative() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "UNIX socket path is not absolute"));
         }
+        Ok(SocketAddr::unix(path))
     }
 
-    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+    /// Parses a URL-style address as used by Docker and similar tools: 'tcp://127.0.0.1:8080',
+    /// 'unix:///run/app.sock' or 'unix:/run/app.sock'.
+    ///
+    /// Whatever follows 'unix://' is parsed like the part after 'unix:' in `FromStr`, so
+    /// 'unix://app.sock' refers to a relative path. TCP hosts must be IP addresses, names aren't
+    /// resolved.
+    #[cfg(feature = "parsing")]
+    pub fn from_url(s: &str) -> Result<SocketAddr, ParseSocketAddrError> {
+        if let Some(rest) = s.strip_prefix("tcp://") {
+            let rest = rest.strip_suffix('/').unwrap_or(rest);
+            return rest.parse().map(SocketAddr::Inet).map_err(ParseSocketAddrError::Inet);
+        }
+        if let Some(rest) = s.strip_prefix("unix://") {
+            return format!("unix:{}", rest).parse();
+        }
+        if s.starts_with("unix:") {
+            return s.parse();
+        }
+        Err(ParseSocketAddrError::UnknownScheme)
+    }
+
+    /// Parses a comma-separated list of addresses, such as '127.0.0.1:80,unix:/run/app.sock'.
+    ///
+    /// Whitespace around each element is ignored. UNIX socket paths containing a comma can't be
+    /// given this way. If an element fails to parse, the error says which one.
+    #[cfg(feature = "parsing")]
+    pub fn parse_many(s: &str) -> Result<Vec<SocketAddr>, ParseSocketAddrError> {
+        s.split(',').enumerate().map(|(index, a)| {
+            a.trim().parse().map_err(|e| ParseSocketAddrError::InList { index, error: Box::new(e) })
+        }).collect()
+    }
+
+    /// Returns `true` for a `SocketAddr::Unix` with a relative path.
+    pub fn is_relative_unix(&self) -> bool {
         match self {
-            Stream::Inet(s) => s.local_addr().map(SocketAddr::Inet),
             #[cfg(unix)]
-            Stream::Unix(s) => s.local_addr().map(|e| e.into())
+            SocketAddr::Unix(p) => p.is_relative(),
+            _ => false,
         }
     }
 
-    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+    pub fn is_unix(&self) -> bool {
         match self {
-            Stream::Inet(s) => s.peer_addr().map(SocketAddr::Inet),
             #[cfg(unix)]
-            Stream::Unix(s) => s.peer_addr().map(|e| e.into())
+            SocketAddr::Unix(_) => true,
+            #[cfg(unix)]
+            SocketAddr::UnnamedUnix => true,
+            #[cfg(target_os = "linux")]
+            SocketAddr::AbstractUnix(_) => true,
+            _ => false,
         }
     }
 
-    pub fn set_read_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+    /// Returns `true` for `SocketAddr::UnnamedUnix`, e.g. the peer address of an unbound client.
+    pub fn is_unnamed(&self) -> bool {
         match self {
-            Stream::Inet(s) => s.set_read_timeout(t),
             #[cfg(unix)]
-            Stream::Unix(s) => s.set_read_timeout(t)
+            SocketAddr::UnnamedUnix => true,
+            _ => false,
         }
     }
 
-    pub fn set_write_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+    pub fn is_inet(&self) -> bool {
         match self {
-            Stream::Inet(s) => s.set_write_timeout(t),
+            SocketAddr::Inet(_) => true,
             #[cfg(unix)]
-            Stream::Unix(s) => s.set_write_timeout(t)
+            _ => false,
         }
     }
 
-    pub fn shutdown(&self, t: net::Shutdown) -> io::Result<()> {
+    pub fn is_ipv4(&self) -> bool {
         match self {
-            Stream::Inet(s) => s.shutdown(t),
+            SocketAddr::Inet(s) => s.is_ipv4(),
             #[cfg(unix)]
-            Stream::Unix(s) => s.shutdown(t)
+            _ => false,
         }
     }
 
-    pub fn try_clone(&self) -> io::Result<Self>{
-        match self{
-            Stream::Inet(stream) => {
-                match stream.try_clone(){
-                    Ok(new_stream) => {
-                        Ok(Self::from(new_stream))
-                    }
-                    Err(err) => {Err(err)}
-                }
-            }
+    pub fn is_ipv6(&self) -> bool {
+        match self {
+            SocketAddr::Inet(s) => s.is_ipv6(),
             #[cfg(unix)]
-            Stream::Unix(stream) => {
-                match stream.try_clone(){
-                    Ok(new_stream) => {
-                        Ok(Self::from(new_stream))
-                    }
-                    Err(err) => {Err(err)}
-                }
-            }
+            _ => false,
         }
     }
-}
 
-impl io::Read for &Stream {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    pub fn family(&self) -> Family {
         match self {
-            Stream::Inet(s) => (&mut (&*s)).read(buf),
+            SocketAddr::Inet(net::SocketAddr::V4(_)) => Family::V4,
+            SocketAddr::Inet(net::SocketAddr::V6(_)) => Family::V6,
             #[cfg(unix)]
-            Stream::Unix(s) => (&mut (&*s)).read(buf)
+            _ => Family::Unix,
         }
     }
 
-    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+    /// Returns the address with an IPv4-mapped IPv6 address (`::ffff:a.b.c.d`) converted to
+    /// plain IPv4, for comparing addresses that may come from a dual-stack socket. The port is
+    /// kept; everything else is returned unchanged.
+    ///
+    /// `==` stays exact, so compare `a.normalized() == b.normalized()` where both forms should
+    /// match.
+    pub fn normalized(&self) -> SocketAddr {
         match self {
-            Stream::Inet(s) => (&mut (&*s)).read_vectored(bufs),
-            #[cfg(unix)]
-            Stream::Unix(s) => (&mut (&*s)).read_vectored(bufs)
+            SocketAddr::Inet(net::SocketAddr::V6(a)) => match a.ip().to_ipv4_mapped() {
+                Some(ip) => SocketAddr::inet((ip, a.port())),
+                None => self.clone(),
+            },
+            _ => self.clone(),
         }
     }
-}
 
-impl io::Write for &Stream {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    /// Returns whether this is an inet address within the CIDR range `cidr`, such as `10.0.0.0/8`
+    /// or `fd00::/8`, e.g. to check peers against an allowlist.
+    ///
+    /// The address is `normalized()` first, so that IPv4 clients of a dual-stack listener match
+    /// IPv4 ranges. UNIX addresses, ranges of the other IP version and malformed ranges never
+    /// match.
+    #[cfg(feature = "parsing")]
+    pub fn in_cidr(&self, cidr: &str) -> bool {
+        let range = || -> Option<(net::IpAddr, u32)> {
+            let (ip, len) = cidr.split_once('/')?;
+            Some((ip.parse().ok()?, len.parse().ok()?))
+        };
+        let (a, b, len, bits) = match (self.normalized().as_inet().map(|a| a.ip()), range()) {
+            (Some(net::IpAddr::V4(a)), Some((net::IpAddr::V4(b), len))) => (u32::from(a) as u128, u32::from(b) as u128, len, 32),
+            (Some(net::IpAddr::V6(a)), Some((net::IpAddr::V6(b), len))) => (u128::from(a), u128::from(b), len, 128),
+            _ => return false,
+        };
+        len <= bits && (a ^ b).checked_shr(bits - len).unwrap_or(0) == 0
+    }
+
+    /// Returns a `Display` implementation that writes the address without the `unix:` prefix,
+    /// e.g. for passing a socket path to tools that expect a bare path.
+    ///
+    /// Inet addresses are written as with `Display`, and abstract names as `@name`. Unnamed UNIX
+    /// addresses have no path and are written as an empty string. Unlike `Display`, the output
+    /// can't be parsed back into the same address.
+    #[cfg(feature = "parsing")]
+    pub fn display_raw(&self) -> impl fmt::Display + '_ {
+        RawDisplay(self)
+    }
+
+    /// Borrows the inner `std::net::SocketAddr`, or returns `None` for UNIX sockets.
+    pub fn as_inet(&self) -> Option<&net::SocketAddr> {
         match self {
-            Stream::Inet(s) => (&mut (&*s)).write(buf),
+            SocketAddr::Inet(s) => Some(s),
             #[cfg(unix)]
-            Stream::Unix(s) => (&mut (&*s)).write(buf)
+            _ => None,
         }
     }
 
-    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> {
+    /// Borrows the path of a UNIX socket, or returns `None` for inet addresses and abstract
+    /// UNIX sockets.
+    #[cfg(unix)]
+    pub fn as_unix_path(&self) -> Option<&Path> {
         match self {
-            Stream::Inet(s) => (&mut (&*s)).write_vectored(bufs),
-            #[cfg(unix)]
-            Stream::Unix(s) => (&mut (&*s)).write_vectored(bufs)
+            SocketAddr::Unix(p) => Some(p),
+            _ => None,
         }
     }
 
-    fn flush(&mut self) -> io::Result<()> {
+    /// Returns the port number for inet addresses, or `None` for UNIX sockets.
+    pub fn port(&self) -> Option<u16> {
         match self {
-            Stream::Inet(s) => (&mut (&*s)).flush(),
+            SocketAddr::Inet(s) => Some(s.port()),
             #[cfg(unix)]
-            Stream::Unix(s) => (&mut (&*s)).flush()
+            _ => None,
         }
     }
-}
 
-impl io::Read for Stream {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { (&mut &*self).read(buf) }
-    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> { (&mut &*self).read_vectored(bufs) }
-}
+    /// Returns the IP address for inet addresses, or `None` for UNIX sockets.
+    pub fn ip(&self) -> Option<net::IpAddr> {
+        match self {
+            SocketAddr::Inet(s) => Some(s.ip()),
+            #[cfg(unix)]
+            _ => None,
+        }
+    }
 
-impl io::Write for Stream {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { (&mut &*self).write(buf) }
-    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> { (&mut &*self).write_vectored(bufs) }
-    fn flush(&mut self) -> io::Result<()> { (&mut &*self).flush() }
+    /// Returns a copy of the address with the port replaced, e.g. to listen on a neighbouring
+    /// port of a configured address. UNIX addresses have no port and are returned unchanged.
+    pub fn with_port(&self, port: u16) -> SocketAddr {
+        match self {
+            SocketAddr::Inet(s) => {
+                let mut s = *s;
+                s.set_port(port);
+                SocketAddr::Inet(s)
+            },
+            #[cfg(unix)]
+            _ => self.clone(),
+        }
+    }
 }
 
 
 
 
 #[derive(Debug)]
-pub enum Listener {
-    Inet(net::TcpListener),
-    #[cfg(unix)]
-    Unix(unix::UnixListener)
+pub enum Stream {
+    #[cfg(feature = "tcp")]
+    Inet(net::TcpStream),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(unix::UnixStream)
 }
 
-impl From<net::TcpListener> for Listener {
-    fn from(s: net::TcpListener) -> Listener {
-        Listener::Inet(s)
+#[cfg(feature = "tcp")]
+impl From<net::TcpStream> for Stream {
+    fn from(s: net::TcpStream) -> Stream {
+        Stream::Inet(s)
     }
 }
 
-#[cfg(unix)]
-impl From<unix::UnixListener> for Listener {
-    fn from(s: unix::UnixListener) -> Listener {
-        Listener::Unix(s)
+#[cfg(all(unix, feature = "unix"))]
+impl From<unix::UnixStream> for Stream {
+    fn from(s: unix::UnixStream) -> Stream {
+        Stream::Unix(s)
     }
 }
 
-impl Listener {
-    pub fn bind(s: &SocketAddr) -> io::Result<Listener> {
+impl Stream {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", fields(family = %s.family()), err))]
+    pub fn connect(s: &SocketAddr) -> io::Result<Stream> {
         match s {
-            SocketAddr::Inet(s) => net::TcpListener::bind(s).map(Listener::Inet),
-            #[cfg(unix)]
-            SocketAddr::Unix(s) => unix::UnixListener::bind(s).map(Listener::Unix)
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(s) => net::TcpStream::connect(s).map(Stream::Inet),
+            #[cfg(not(feature = "tcp"))]
+            SocketAddr::Inet(_) => Err(tcp_disabled()),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(s) => unix::UnixStream::connect(s).map(Stream::Unix),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::UnnamedUnix => Err(unnamed_addr()),
+            #[cfg(all(target_os = "linux", feature = "unix"))]
+            SocketAddr::AbstractUnix(n) => {
+                use std::os::linux::net::SocketAddrExt;
+                let a = unix::SocketAddr::from_abstract_name(n)?;
+                unix::UnixStream::connect_addr(&a).map(Stream::Unix)
+            },
+            #[cfg(all(unix, not(feature = "unix")))]
+            _ => Err(unix_disabled())
         }
     }
 
-    /// Same as `bind()`, but for UNIX sockets this will try to re-bind to the path if the process
-    /// that used to listen to this address is no longer running. It can also optionally set the
-    /// permissions of the UNIX socket.
+    /// Same as `connect()`, but takes anything that converts into a `SocketAddr`, such as a
+    /// `std::net::SocketAddr`.
+    pub fn connect_into<A: Into<SocketAddr>>(a: A) -> io::Result<Stream> {
+        Self::connect(&a.into())
+    }
+
+    /// Creates a pair of connected streams.
     ///
-    /// # Limitations
+    /// This is a UNIX socket pair on UNIX-like platforms; elsewhere, or with the `unix` feature
+    /// disabled, it is a TCP connection over the loopback interface.
+    pub fn pair() -> io::Result<(Stream, Stream)> {
+        #[cfg(all(unix, feature = "unix"))]
+        {
+            unix::UnixStream::pair().map(|(a, b)| (Stream::Unix(a), Stream::Unix(b)))
+        }
+        #[cfg(not(all(unix, feature = "unix")))]
+        {
+            let l = net::TcpListener::bind((net::Ipv4Addr::LOCALHOST, 0))?;
+            let a = net::TcpStream::connect(l.local_addr()?)?;
+            loop {
+                let (b, peer) = l.accept()?;
+                // Make sure we didn't pick up somebody else's connection.
+                if peer == a.local_addr()? {
+                    return Ok((Stream::Inet(a), Stream::Inet(b)));
+                }
+            }
+        }
+    }
+
+    /// Opens a connection to an address given as a string.
     ///
-    /// Trying to bind to the same UNIX socket path from multiple processes is subject to a race
-    /// condition.
+    /// Addresses prefixed with 'unix:' are connected to directly. Anything else is resolved with
+    /// `std::net::ToSocketAddrs` (so host names such as 'example.com:443' are accepted) and each
+    /// resolved address is tried in turn until one connects. If none does, the error from the
+    /// last attempt is returned.
+    #[cfg(feature = "parsing")]
+    pub fn connect_str(s: &str) -> io::Result<Stream> {
+        if s.starts_with("unix:") {
+            let addr = s.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            return Self::connect(&addr);
+        }
+        let mut last_err = None;
+        for addr in net::ToSocketAddrs::to_socket_addrs(s)? {
+            match Self::connect(&SocketAddr::Inet(addr)) {
+                Ok(s) => return Ok(s),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")
+        }))
+    }
+
+    /// Opens a connection to the given address, failing with `TimedOut` if it isn't established
+    /// within `timeout`.
     ///
-    /// The permissions are set *after* performing the `bind()` operation, so if the default umask
-    /// is less restrictive than the given mode, there is a short window where an unprivileged
-    /// process could attempt to connect to the socket.
-    pub fn bind_reuse(s: &SocketAddr, _mode: Option<u32>) -> io::Result<Listener> {
-        let b = match (Self::bind(s), s) {
+    /// Connecting to a UNIX socket never waits on the network, so the timeout only applies to
+    /// TCP addresses and is ignored for UNIX sockets.
+    pub fn connect_timeout(s: &SocketAddr, timeout: Duration) -> io::Result<Stream> {
+        match s {
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(s) => net::TcpStream::connect_timeout(s, timeout).map(Stream::Inet),
             #[cfg(unix)]
-            (Err(ref e), &SocketAddr::Unix(ref p)) if e.kind() == io::ErrorKind::AddrInUse => {
-                let e = io::Error::last_os_error();
+            _ => { let _ = timeout; Self::connect(s) }
+        }
+    }
 
-                // Make sure it is a socket in the first place (we don't want to overwrite a
-                // regular file)
-                use std::os::unix::fs::FileTypeExt;
-                match std::fs::symlink_metadata(p) {
-                    Ok(ref m) if m.file_type().is_socket() => (),
-                    _ => return Err(e),
-                };
+    /// Opens a TCP connection to `target` through the SOCKS5 proxy at `proxy`, optionally
+    /// authenticating with a username and password.
+    ///
+    /// The returned stream is connected to the proxy, which relays all data to and from the
+    /// target. UNIX socket targets fail with an error of kind `Unsupported`.
+    #[cfg(feature = "tcp")]
+    pub fn connect_via_socks5(target: &SocketAddr, proxy: net::SocketAddr, auth: Option<(String,String)>) -> io::Result<Stream> {
+        let target = match target {
+            SocketAddr::Inet(a) => a,
+            #[cfg(unix)]
+            _ => return Err(unsupported("Connecting through a SOCKS5 proxy")),
+        };
+        let mut s = net::TcpStream::connect(proxy)?;
+        socks5::handshake(&mut s, target, auth.as_ref().map(|(u, p)| (u.as_str(), p.as_str())))?;
+        Ok(Stream::Inet(s))
+    }
 
-                // Try to connect to the socket to see if it's still alive.
-                match Stream::connect(s) {
-                    // Not alive, delete the socket and try to bind again.
-                    Err(ref e2) if e2.kind() == io::ErrorKind::ConnectionRefused
-                        => std::fs::remove_file(p).and_then(|_| Self::bind(s))?,
-                    _ => return Err(e),
+    /// Tries to connect to each address in turn, returning the first stream successfully opened.
+    ///
+    /// With `per_timeout` set, each attempt goes through `connect_timeout()`. If every attempt
+    /// fails, the returned error has the kind of the last failure and a message listing the error
+    /// for each address.
+    pub fn connect_first(addrs: &[SocketAddr], per_timeout: Option<Duration>) -> io::Result<Stream> {
+        let mut errors = Vec::new();
+        let mut kind = io::ErrorKind::InvalidInput;
+        for addr in addrs {
+            let r = match per_timeout {
+                Some(t) => Self::connect_timeout(addr, t),
+                None => Self::connect(addr)
+            };
+            match r {
+                Ok(s) => return Ok(s),
+                Err(e) => {
+                    kind = e.kind();
+                    #[cfg(feature = "parsing")]
+                    errors.push(format!("{}: {}", addr, e));
+                    #[cfg(not(feature = "parsing"))]
+                    errors.push(format!("{:?}: {}", addr, e));
                 }
-            },
-            (Err(e), _) => return Err(e),
-            (Ok(l), _) => l,
+            }
+        }
+        if errors.is_empty() {
+            return Err(io::Error::new(kind, "no addresses to connect to"));
+        }
+        Err(io::Error::new(kind, errors.join("; ")))
+    }
+
+    /// Connects to the given address, retrying while the connection is refused or the UNIX
+    /// socket doesn't exist yet, e.g. because the service is still starting.
+    ///
+    /// Makes up to `attempts` attempts (at least one), sleeping `base_delay` after the first
+    /// failure and doubling the delay after each subsequent one. Other errors, and the error of
+    /// the last attempt, are returned right away.
+    pub fn connect_retry(addr: &SocketAddr, attempts: usize, base_delay: Duration) -> io::Result<Stream> {
+        let mut delay = base_delay;
+        for _ in 1..attempts {
+            match Self::connect(addr) {
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused || e.kind() == io::ErrorKind::NotFound => {
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                },
+                r => return r,
+            }
+        }
+        Self::connect(addr)
+    }
+
+    /// Opens a connection to the given address and applies `options` to the resulting stream.
+    ///
+    /// Options that don't apply to UNIX sockets, like `nodelay`, are skipped for them.
+    pub fn connect_with(s: &SocketAddr, options: &ConnectOptions) -> io::Result<Stream> {
+        let stream = match options.timeout {
+            Some(t) => Self::connect_timeout(s, t)?,
+            None => Self::connect(s)?
         };
+        if let Some(nodelay) = options.nodelay {
+            stream.set_nodelay(nodelay)?;
+        }
+        if options.read_timeout.is_some() {
+            stream.set_read_timeout(options.read_timeout)?;
+        }
+        if options.write_timeout.is_some() {
+            stream.set_write_timeout(options.write_timeout)?;
+        }
+        Ok(stream)
+    }
+
+    /// Constructs a `Stream::Inet` from a raw file descriptor.
+    ///
+    /// # Safety
+    ///
+    /// The `fd` passed in must be an owned file descriptor of a connected TCP socket; in
+    /// particular, it must be open. Ownership is transferred to the returned `Stream`.
+    #[cfg(all(unix, feature = "tcp"))]
+    pub unsafe fn from_tcp_raw_fd(fd: RawFd) -> Stream {
+        Stream::Inet(net::TcpStream::from_raw_fd(fd))
+    }
+
+    /// Constructs a `Stream::Unix` from a raw file descriptor.
+    ///
+    /// # Safety
+    ///
+    /// The `fd` passed in must be an owned file descriptor of a connected UNIX stream socket; in
+    /// particular, it must be open. Ownership is transferred to the returned `Stream`.
+    #[cfg(all(unix, feature = "unix"))]
+    pub unsafe fn from_unix_raw_fd(fd: RawFd) -> Stream {
+        Stream::Unix(unix::UnixStream::from_raw_fd(fd))
+    }
+
+    /// Borrows the inner `TcpStream`, or returns `None` for UNIX sockets.
+    #[cfg(feature = "tcp")]
+    pub fn as_tcp(&self) -> Option<&net::TcpStream> {
+        match self {
+            Stream::Inet(s) => Some(s),
+            #[cfg(all(unix, feature = "unix"))]
+            _ => None
+        }
+    }
+
+    /// Mutably borrows the inner `TcpStream`, or returns `None` for UNIX sockets.
+    #[cfg(feature = "tcp")]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut net::TcpStream> {
+        match self {
+            Stream::Inet(s) => Some(s),
+            #[cfg(all(unix, feature = "unix"))]
+            _ => None
+        }
+    }
+
+    /// Borrows the inner `UnixStream`, or returns `None` for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn as_unix(&self) -> Option<&unix::UnixStream> {
+        match self {
+            Stream::Unix(s) => Some(s),
+            #[cfg(feature = "tcp")]
+            _ => None
+        }
+    }
+
+    /// Mutably borrows the inner `UnixStream`, or returns `None` for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn as_unix_mut(&mut self) -> Option<&mut unix::UnixStream> {
+        match self {
+            Stream::Unix(s) => Some(s),
+            #[cfg(feature = "tcp")]
+            _ => None
+        }
+    }
+
+    /// Takes out the inner `TcpStream`, or gives the stream back for UNIX sockets.
+    #[cfg(feature = "tcp")]
+    pub fn into_tcp(self) -> Result<net::TcpStream, Stream> {
+        match self {
+            Stream::Inet(s) => Ok(s),
+            #[cfg(all(unix, feature = "unix"))]
+            s => Err(s)
+        }
+    }
+
+    /// Takes out the inner `UnixStream`, or gives the stream back for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn into_unix(self) -> Result<unix::UnixStream, Stream> {
+        match self {
+            Stream::Unix(s) => Ok(s),
+            #[cfg(feature = "tcp")]
+            s => Err(s)
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.local_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.local_addr().map(|e| e.into())
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.peer_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.peer_addr().map(|e| e.into())
+        }
+    }
+
+    /// Returns the peer address of a UNIX socket as libstd reports it, or `None` for TCP
+    /// sockets, for when the conversion to `SocketAddr` loses something that matters.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn peer_addr_unix(&self) -> io::Result<Option<unix::SocketAddr>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(_) => Ok(None),
+            Stream::Unix(s) => s.peer_addr().map(Some)
+        }
+    }
+
+    /// Sets the read timeout, or clears it with `None`. A zero duration is rejected with an
+    /// error of kind `InvalidInput`, as it would otherwise be indistinguishable from no timeout.
+    pub fn set_read_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+        check_timeout(t)?;
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.set_read_timeout(t),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.set_read_timeout(t)
+        }
+    }
+
+    /// Same as `set_read_timeout()`, for writes.
+    pub fn set_write_timeout(&self, t: Option<Duration>) -> io::Result<()> {
+        check_timeout(t)?;
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.set_write_timeout(t),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.set_write_timeout(t)
+        }
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.read_timeout(),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.read_timeout()
+        }
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.write_timeout(),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.write_timeout()
+        }
+    }
+
+    /// Sets the value of the `TCP_NODELAY` option on this socket.
+    ///
+    /// UNIX sockets have no equivalent option, so this is a no-op returning `Ok(())` for them.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.set_nodelay(nodelay),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = nodelay; Ok(()) }
+        }
+    }
+
+    /// Gets the value of the `TCP_NODELAY` option on this socket.
+    ///
+    /// Always returns `Ok(false)` for UNIX sockets.
+    pub fn nodelay(&self) -> io::Result<bool> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.nodelay(),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => Ok(false)
+        }
+    }
+
+    /// Sets the value for the `IP_TTL` option on this socket.
+    ///
+    /// Returns an error of kind `Unsupported` for UNIX sockets.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.set_ttl(ttl),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = ttl; Err(unsupported("IP_TTL")) }
+        }
+    }
 
+    /// Gets the value of the `IP_TTL` option for this socket.
+    ///
+    /// Returns an error of kind `Unsupported` for UNIX sockets.
+    pub fn ttl(&self) -> io::Result<u32> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.ttl(),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => Err(unsupported("IP_TTL"))
+        }
+    }
+
+    /// Sets the type-of-service byte (DSCP and ECN bits) of outgoing packets, using `IP_TOS` or
+    /// `IPV6_TCLASS` depending on the address family.
+    ///
+    /// Only supported on UNIX-like platforms; elsewhere and for UNIX sockets an error of kind
+    /// `Unsupported` is returned.
+    pub fn set_tos(&self, tos: u32) -> io::Result<()> {
+        match self {
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => sys::set_tos(s.as_raw_fd(), s.local_addr()?.is_ipv6(), tos),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => { let _ = tos; Err(unsupported("IP_TOS")) },
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = tos; Err(unsupported("IP_TOS")) }
+        }
+    }
+
+    /// Gets the type-of-service byte set with `set_tos()`.
+    pub fn tos(&self) -> io::Result<u32> {
+        match self {
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => sys::tos(s.as_raw_fd(), s.local_addr()?.is_ipv6()),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => Err(unsupported("IP_TOS")),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => Err(unsupported("IP_TOS"))
+        }
+    }
+
+    /// Sets a socket option the crate doesn't wrap, passing `value` to `setsockopt()` as is.
+    ///
+    /// Nothing checks that `value` has the layout the option expects, so this is on the same
+    /// footing as calling `setsockopt()` directly: options that contain pointers, like socket
+    /// filters, make the kernel access whatever memory they point to. Only supported on UNIX-like
+    /// platforms; elsewhere an error of kind `Unsupported` is returned.
+    pub fn set_sockopt_raw(&self, level: i32, name: i32, value: &[u8]) -> io::Result<()> {
         #[cfg(unix)]
-        #[allow(clippy::single_match)]
-        match (_mode, s) {
-            (Some(perm), &SocketAddr::Unix(ref p)) => {
-                use std::fs::{set_permissions,Permissions};
-                use std::os::unix::fs::PermissionsExt;
-                set_permissions(p, Permissions::from_mode(perm))?;
+        {
+            sys::setsockopt_raw(self.as_raw_fd(), level, name, value)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (level, name, value);
+            Err(unsupported("Setting raw socket options"))
+        }
+    }
+
+    /// Reads a socket option the crate doesn't wrap into `buf`, returning the length of the value.
+    ///
+    /// Only supported on UNIX-like platforms; elsewhere an error of kind `Unsupported` is
+    /// returned.
+    pub fn get_sockopt_raw(&self, level: i32, name: i32, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        {
+            sys::getsockopt_raw(self.as_raw_fd(), level, name, buf)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (level, name, buf);
+            Err(unsupported("Getting raw socket options"))
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.set_nonblocking(nonblocking),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.set_nonblocking(nonblocking)
+        }
+    }
+
+    /// Sets or clears `FD_CLOEXEC`, which libstd sets on all sockets it creates. Clear it to let
+    /// a child process started with `exec()` inherit the socket.
+    #[cfg(unix)]
+    pub fn set_cloexec(&self, cloexec: bool) -> io::Result<()> {
+        sys::set_cloexec(self.as_raw_fd(), cloexec)
+    }
+
+    /// Waits until the stream is readable, i.e. a read won't block, or `timeout` passes.
+    /// Returns whether it became readable; with no timeout, waits as long as it takes.
+    ///
+    /// EOF and pending errors count as readable, since reading reports them without blocking.
+    /// Only supported on UNIX-like platforms; elsewhere an error of kind `Unsupported` is
+    /// returned.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        #[cfg(unix)]
+        {
+            sys::poll(self.as_raw_fd(), libc::POLLIN, timeout)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = timeout;
+            Err(unsupported("Waiting for readiness"))
+        }
+    }
+
+    /// Same as `wait_readable()`, but waits until a write won't block, e.g. because the peer has
+    /// read enough of the data queued before.
+    pub fn wait_writable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        #[cfg(unix)]
+        {
+            sys::poll(self.as_raw_fd(), libc::POLLOUT, timeout)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = timeout;
+            Err(unsupported("Waiting for readiness"))
+        }
+    }
+
+    /// Receives data on the socket from the remote address to which it is connected, without
+    /// removing that data from the queue. On success, returns the number of bytes peeked.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.peek(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => sys::recv(s.as_raw_fd(), buf, libc::MSG_PEEK)
+        }
+    }
+
+    /// Sends data with the given `send(2)` flags, such as `libc::MSG_DONTWAIT` or
+    /// `libc::MSG_OOB`. The flags are passed through unchecked.
+    #[cfg(unix)]
+    pub fn send_flags(&self, buf: &[u8], flags: i32) -> io::Result<usize> {
+        sys::send(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Receives data with the given `recv(2)` flags, such as `libc::MSG_DONTWAIT` or
+    /// `libc::MSG_WAITALL`. The flags are passed through unchecked.
+    #[cfg(unix)]
+    pub fn recv_flags(&self, buf: &mut [u8], flags: i32) -> io::Result<usize> {
+        sys::recv(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Sends `byte` as TCP urgent data, using `MSG_OOB`.
+    ///
+    /// Urgent data is a legacy feature with inconsistent behaviour: only one byte is tracked at a
+    /// time, so a new urgent byte replaces one the peer hasn't read yet, and stacks disagree on
+    /// where the urgent pointer points (see RFC 6093). Unless the receiver enabled
+    /// `set_oob_inline()`, the byte is taken out of the regular stream and can only be read with
+    /// `recv_oob()`. Only supported on UNIX-like platforms; elsewhere and for UNIX sockets an error
+    /// of kind `Unsupported` is returned.
+    pub fn send_oob(&self, byte: u8) -> io::Result<()> {
+        match self {
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => sys::send(s.as_raw_fd(), &[byte], libc::MSG_OOB).map(|_| ()),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => { let _ = byte; Err(unsupported("TCP urgent data")) },
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = byte; Err(unsupported("TCP urgent data")) }
+        }
+    }
+
+    /// Reads the pending urgent byte sent with `send_oob()`, using `MSG_OOB`.
+    ///
+    /// Fails with `EINVAL` (kind `InvalidInput`) on Linux when no urgent byte is pending or
+    /// `SO_OOBINLINE` is enabled, and with `WouldBlock` on some other platforms. Only supported on
+    /// UNIX-like platforms; elsewhere and for UNIX sockets an error of kind `Unsupported` is
+    /// returned.
+    pub fn recv_oob(&self) -> io::Result<u8> {
+        match self {
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => {
+                let mut byte = [0];
+                match sys::recv(s.as_raw_fd(), &mut byte, libc::MSG_OOB)? {
+                    0 => Err(io::ErrorKind::UnexpectedEof.into()),
+                    _ => Ok(byte[0]),
+                }
             },
-            _ => (),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => Err(unsupported("TCP urgent data")),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => Err(unsupported("TCP urgent data"))
         }
-        Ok(b)
     }
 
-    pub fn accept(&self) -> io::Result<(Stream,SocketAddr)> {
+    /// Sets `SO_OOBINLINE`, which makes urgent bytes arrive within the regular stream at the
+    /// position they were sent instead of being read separately with `recv_oob()`.
+    ///
+    /// Only supported on UNIX-like platforms; elsewhere and for UNIX sockets an error of kind
+    /// `Unsupported` is returned.
+    pub fn set_oob_inline(&self, on: bool) -> io::Result<()> {
         match self {
-            Listener::Inet(l) => l.accept().map(|(s,e)| (s.into(), e.into())),
-            #[cfg(unix)]
-            Listener::Unix(l) => l.accept().map(|(s,e)| (s.into(), e.into()))
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => sys::setsockopt(s.as_raw_fd(), libc::SOL_SOCKET, libc::SO_OOBINLINE, on as libc::c_int),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => { let _ = on; Err(unsupported("SO_OOBINLINE")) },
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = on; Err(unsupported("SO_OOBINLINE")) }
+        }
+    }
+
+    /// Gets the value of the `SO_OOBINLINE` option set with `set_oob_inline()`.
+    pub fn oob_inline(&self) -> io::Result<bool> {
+        match self {
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => sys::getsockopt::<libc::c_int>(s.as_raw_fd(), libc::SOL_SOCKET, libc::SO_OOBINLINE)
+                .map(|v| v != 0),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => Err(unsupported("SO_OOBINLINE")),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => Err(unsupported("SO_OOBINLINE"))
+        }
+    }
+
+    /// Returns the credentials of the process on the other end of a UNIX socket.
+    ///
+    /// The pid is only available on Linux; other platforms only report the uid and gid. Returns
+    /// an error of kind `Unsupported` for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn peer_cred(&self) -> io::Result<PeerCred> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(_) => Err(unsupported("Peer credential lookup")),
+            Stream::Unix(s) => sys::peer_cred(s.as_raw_fd()).map(|(uid, gid, pid)| PeerCred::new(uid, gid, pid))
         }
     }
-}
 
+    /// Checks whether the connection still looks alive, without consuming any data.
+    ///
+    /// This is a best-effort snapshot, not a guarantee: a closed connection is only noticed once
+    /// the peer's FIN or RST has arrived and any data it sent before has been read, and a peer
+    /// that merely shut down its write side is reported as disconnected too. Pending errors are
+    /// cleared as with `take_error()`. On platforms other than UNIX-like ones only `take_error()`
+    /// and `peer_addr()` are checked.
+    pub fn is_connected(&self) -> bool {
+        if !matches!(self.take_error(), Ok(None)) || self.peer_addr().is_err() {
+            return false;
+        }
+        #[cfg(unix)]
+        {
+            let mut buf = [0; 1];
+            match sys::recv(self.as_raw_fd(), &mut buf, libc::MSG_PEEK | libc::MSG_DONTWAIT) {
+                Ok(0) => false,
+                Ok(_) => true,
+                Err(e) => e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted,
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            true
+        }
+    }
 
+    /// Peeks at the first bytes waiting on the socket and looks up the peer's credentials, for
+    /// deciding how to handle a new connection.
+    ///
+    /// Combines `peek()` and `peer_cred()`; the credentials are `None` for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn inspect(&self, peek_buf: &mut [u8]) -> io::Result<(usize, Option<PeerCred>)> {
+        let cred = match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(_) => None,
+            Stream::Unix(_) => Some(self.peer_cred()?)
+        };
+        Ok((self.peek(peek_buf)?, cred))
+    }
 
+    /// Gets the value of the `SO_ERROR` option on this socket, clearing it in the process.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.take_error(),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.take_error()
+        }
+    }
 
-#[test]
-fn test_socket_addr_inet() {
-    let ip4 = "127.0.0.1:10".parse::<net::SocketAddr>().unwrap();
-    let ip6 = "[::20]:10".parse::<net::SocketAddr>().unwrap();
-    assert_eq!(ip4.to_string(), "127.0.0.1:10".parse::<SocketAddr>().unwrap().to_string());
-    assert_eq!(ip4.to_string(), SocketAddr::from(ip4).to_string());
-    assert_eq!(ip6.to_string(), "[::20]:10".parse::<SocketAddr>().unwrap().to_string());
-    assert_eq!(ip6.to_string(), SocketAddr::from(ip6).to_string());
-}
+    /// Enables or disables `SO_KEEPALIVE` on a TCP socket. When enabled, the given duration is
+    /// used as the idle time before the first keepalive probe is sent.
+    ///
+    /// The idle time is rounded to whole seconds. Support for it varies by platform: Linux,
+    /// Android, the BSDs and macOS honour it, elsewhere only `SO_KEEPALIVE` itself is toggled and
+    /// the system default interval applies. Keepalive is currently only implemented on UNIX-like
+    /// platforms; on others, and for UNIX sockets, an error of kind `Unsupported` is returned.
+    pub fn set_keepalive(&self, keepalive: Option<Duration>) -> io::Result<()> {
+        match self {
+            #[cfg(all(unix, feature = "tcp"))]
+            Stream::Inet(s) => sys::set_keepalive(s.as_raw_fd(), keepalive),
+            #[cfg(not(unix))]
+            Stream::Inet(_) => { let _ = keepalive; Err(unsupported("TCP keepalive")) },
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = keepalive; Err(unsupported("TCP keepalive")) }
+        }
+    }
 
-#[test]
-#[cfg(unix)]
-fn test_socket_addr_unix() {
-    assert_eq!("unix:/tmp/sock".parse::<SocketAddr>().unwrap().to_string(), "unix:/tmp/sock");
-    assert!("/tmp/sock".parse::<SocketAddr>().is_err());
+    /// Sets the value of the `SO_LINGER` option on this socket, rounded to whole seconds.
+    ///
+    /// With a duration set, closing the socket blocks until unsent data has been transmitted or
+    /// the duration has passed; a zero duration discards unsent data (and resets TCP connections)
+    /// immediately. Only supported on UNIX-like platforms; elsewhere an error of kind
+    /// `Unsupported` is returned.
+    pub fn set_linger(&self, dur: Option<Duration>) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            sys::set_linger(self.as_raw_fd(), dur)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = dur;
+            Err(unsupported("SO_LINGER"))
+        }
+    }
+
+    /// Gets the value of the `SO_LINGER` option on this socket.
+    ///
+    /// Only supported on UNIX-like platforms; elsewhere an error of kind `Unsupported` is
+    /// returned.
+    pub fn linger(&self) -> io::Result<Option<Duration>> {
+        #[cfg(unix)]
+        {
+            sys::linger(self.as_raw_fd())
+        }
+        #[cfg(not(unix))]
+        {
+            Err(unsupported("SO_LINGER"))
+        }
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`) of this socket.
+    ///
+    /// The kernel may round the requested value or, as Linux does, double it to account for
+    /// bookkeeping overhead; use `send_buffer_size()` to see what was actually applied. Only
+    /// supported on UNIX-like platforms; elsewhere an error of kind `Unsupported` is returned.
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            sys::set_buffer_size(self.as_raw_fd(), libc::SO_SNDBUF, size)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = size;
+            Err(unsupported("SO_SNDBUF"))
+        }
+    }
+
+    /// Gets the size of the send buffer (`SO_SNDBUF`) of this socket.
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        #[cfg(unix)]
+        {
+            sys::buffer_size(self.as_raw_fd(), libc::SO_SNDBUF)
+        }
+        #[cfg(not(unix))]
+        {
+            Err(unsupported("SO_SNDBUF"))
+        }
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`) of this socket.
+    ///
+    /// The same caveats as for `set_send_buffer_size()` apply.
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            sys::set_buffer_size(self.as_raw_fd(), libc::SO_RCVBUF, size)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = size;
+            Err(unsupported("SO_RCVBUF"))
+        }
+    }
+
+    /// Gets the size of the receive buffer (`SO_RCVBUF`) of this socket.
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        #[cfg(unix)]
+        {
+            sys::buffer_size(self.as_raw_fd(), libc::SO_RCVBUF)
+        }
+        #[cfg(not(unix))]
+        {
+            Err(unsupported("SO_RCVBUF"))
+        }
+    }
+
+    /// Sends data over a UNIX socket together with a set of file descriptors (using
+    /// `SCM_RIGHTS`), returning the number of bytes sent.
+    ///
+    /// The descriptors are duplicated into the receiving process; the caller keeps ownership of
+    /// the ones passed here. At least one byte of data should be sent along with them. Like
+    /// `write()`, this fails with `BrokenPipe` rather than raising `SIGPIPE` if the peer has gone
+    /// away. Returns an error of kind `Unsupported` for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(_) => Err(unsupported("File descriptor passing")),
+            Stream::Unix(s) => sys::send_with_fds(s.as_raw_fd(), buf, fds)
+        }
+    }
+
+    /// Receives data from a UNIX socket along with any file descriptors sent using `SCM_RIGHTS`,
+    /// returning the number of bytes and file descriptors received.
+    ///
+    /// The received descriptors are owned by the caller, and have close-on-exec set where the
+    /// platform supports it. If more descriptors were sent than fit into `fds` (including when
+    /// `fds` is empty), the kernel discards the rest; in that case the received ones are closed
+    /// and an error of kind `InvalidData` is returned, with the data received along with them
+    /// lost. Returns an error of kind `Unsupported` for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn recv_with_fds(&self, buf: &mut [u8], fds: &mut [RawFd]) -> io::Result<(usize, usize)> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(_) => Err(unsupported("File descriptor passing")),
+            Stream::Unix(s) => sys::recv_with_fds(s.as_raw_fd(), buf, fds)
+        }
+    }
+
+    /// Reads exactly enough bytes to fill `buf`, failing with `TimedOut` if that doesn't happen
+    /// before `timeout` has passed.
+    ///
+    /// The read timeout of the socket is adjusted to the remaining time before every read, and
+    /// restored to its previous value afterwards. The stream must be in blocking mode. On error,
+    /// the contents of `buf` and the amount of data consumed from the stream are unspecified.
+    pub fn read_exact_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<()> {
+        let old = self.read_timeout()?;
+        let r = self.read_exact_until(buf, Instant::now() + timeout);
+        self.set_read_timeout(old)?;
+        r
+    }
+
+    fn read_exact_until(&self, mut buf: &mut [u8], deadline: Instant) -> io::Result<()> {
+        use std::io::Read;
+        while !buf.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out before the buffer was filled"));
+            }
+            self.set_read_timeout(Some(remaining))?;
+            match (&mut &*self).read(buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+                Ok(n) => buf = &mut buf[n..],
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes all of `bufs`, calling `write_vectored()` until every buffer is fully written.
+    ///
+    /// Like the unstable `Write::write_all_vectored()`, the contents of `bufs` are modified to
+    /// track progress, so they are unspecified afterwards.
+    pub fn write_all_vectored(&self, mut bufs: &mut [io::IoSlice<'_>]) -> io::Result<()> {
+        use std::io::Write;
+        io::IoSlice::advance_slices(&mut bufs, 0);
+        while !bufs.is_empty() {
+            match (&mut &*self).write_vectored(bufs) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer")),
+                Ok(n) => io::IoSlice::advance_slices(&mut bufs, n),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a frame header followed by its body, passing both to a single `write_vectored()`
+    /// call instead of writing them separately. Partial writes are continued across the two
+    /// buffers until everything has been sent.
+    pub fn send_framed(&self, header: &[u8], body: &[u8]) -> io::Result<()> {
+        self.write_all_vectored(&mut [io::IoSlice::new(header), io::IoSlice::new(body)])
+    }
+
+    /// Reads all bytes until EOF into `buf` like `Read::read_to_end()`, but sizes each read by
+    /// the number of bytes already queued on the socket, reducing syscalls for large payloads.
+    ///
+    /// Falls back to the plain `read_to_end()` when the amount can't be queried.
+    pub fn read_to_end_hinted(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        use std::io::Read;
+        #[cfg(unix)]
+        {
+            const MIN_READ: usize = 8 * 1024;
+            let start = buf.len();
+            loop {
+                let hint = match sys::bytes_available(self.as_raw_fd()) {
+                    Ok(n) => n.max(MIN_READ),
+                    Err(_) => return (&mut &*self).read_to_end(buf).map(|_| buf.len() - start),
+                };
+                let len = buf.len();
+                buf.resize(len + hint, 0);
+                match (&mut &*self).read(&mut buf[len..]) {
+                    Ok(0) => {
+                        buf.truncate(len);
+                        return Ok(len - start);
+                    },
+                    Ok(n) => buf.truncate(len + n),
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => buf.truncate(len),
+                    Err(e) => {
+                        buf.truncate(len);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            (&mut &*self).read_to_end(buf)
+        }
+    }
+
+    /// Reads a line, up to and including the `\n`, and appends it to `buf`, failing with
+    /// `TimedOut` if the line isn't complete within `timeout`. Returns the number of bytes read,
+    /// which is 0 at EOF.
+    ///
+    /// Data is peeked first so that nothing past the newline is consumed, meaning no buffer has
+    /// to be kept between calls. The read timeout is changed while reading and restored after.
+    /// On a timeout, the part of the line read so far is still appended to `buf` if it is valid
+    /// UTF-8.
+    pub fn read_line(&self, buf: &mut String, timeout: Duration) -> io::Result<usize> {
+        use std::io::Read;
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "line not complete before the timeout");
+        let deadline = Instant::now() + timeout;
+        let prev_timeout = self.read_timeout()?;
+        let mut line = Vec::new();
+        let mut chunk = [0; 1024];
+        let r = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                break Err(timed_out());
+            }
+            if let Err(e) = self.set_read_timeout(Some(remaining)) {
+                break Err(e);
+            }
+            let n = match self.peek(&mut chunk) {
+                Ok(0) => break Ok(()),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                    break Err(timed_out());
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e),
+            };
+            let end = chunk[..n].iter().position(|&b| b == b'\n').map_or(n, |i| i + 1);
+            if let Err(e) = (&mut &*self).read_exact(&mut chunk[..end]) {
+                break Err(e);
+            }
+            line.extend_from_slice(&chunk[..end]);
+            if chunk[end - 1] == b'\n' {
+                break Ok(());
+            }
+        };
+        self.set_read_timeout(prev_timeout)?;
+
+        let text = std::str::from_utf8(&line);
+        if let Ok(text) = text {
+            buf.push_str(text);
+        }
+        r?;
+        text.map(|_| line.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+    }
+
+    /// Reads up to `max` bytes into the spare capacity of `buf`, reserving it first, and grows
+    /// the length of `buf` by the number of bytes read.
+    ///
+    /// Unlike reading into a slice, the new space doesn't have to be zeroed first. On platforms
+    /// other than UNIX, it is zeroed anyway.
+    pub fn read_into_spare(&self, buf: &mut Vec<u8>, max: usize) -> io::Result<usize> {
+        buf.reserve(max);
+        #[cfg(unix)]
+        {
+            let n = sys::recv_uninit(self.as_raw_fd(), &mut buf.spare_capacity_mut()[..max], 0)?;
+            // recv() initialized the first n bytes of the spare capacity.
+            unsafe { buf.set_len(buf.len() + n) };
+            Ok(n)
+        }
+        #[cfg(not(unix))]
+        {
+            use std::io::Read;
+            let len = buf.len();
+            buf.resize(len + max, 0);
+            let r = (&mut &*self).read(&mut buf[len..]);
+            buf.truncate(len + *r.as_ref().unwrap_or(&0));
+            r
+        }
+    }
+
+    /// Sets the `SO_MARK` (fwmark) of a TCP socket, for use in policy routing and filtering.
+    ///
+    /// This requires the `CAP_NET_ADMIN` capability. Only supported on Linux; on other platforms
+    /// and for UNIX sockets an error of kind `Unsupported` is returned.
+    pub fn set_mark(&self, mark: u32) -> io::Result<()> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "tcp"))]
+            Stream::Inet(s) => sys::setsockopt(s.as_raw_fd(), libc::SOL_SOCKET, libc::SO_MARK, mark),
+            #[cfg(all(not(target_os = "linux"), feature = "tcp"))]
+            Stream::Inet(_) => { let _ = mark; Err(unsupported("SO_MARK")) },
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(_) => { let _ = mark; Err(unsupported("SO_MARK")) }
+        }
+    }
+
+    pub fn shutdown(&self, t: net::Shutdown) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.shutdown(t),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => s.shutdown(t)
+        }
+    }
+
+    /// Shuts down the read half of the connection, same as `shutdown(Shutdown::Read)`.
+    pub fn shutdown_read(&self) -> io::Result<()> {
+        self.shutdown(net::Shutdown::Read)
+    }
+
+    /// Shuts down the write half of the connection, same as `shutdown(Shutdown::Write)`. The
+    /// peer will see EOF once it has read any data sent before.
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.shutdown(net::Shutdown::Write)
+    }
+
+    /// Shuts down both halves of the connection, same as `shutdown(Shutdown::Both)`.
+    pub fn shutdown_both(&self) -> io::Result<()> {
+        self.shutdown(net::Shutdown::Both)
+    }
+
+    /// Flushes the stream, shuts down the write half and closes the socket, returning any error
+    /// that dropping the stream would silently ignore.
+    ///
+    /// A peer that has already gone away doesn't count as an error for the shutdown. The socket
+    /// is closed even if flushing or shutting down fails.
+    pub fn close(mut self) -> io::Result<()> {
+        let flushed = io::Write::flush(&mut self).and_then(|_| match self.shutdown(net::Shutdown::Write) {
+            Err(e) if e.kind() == io::ErrorKind::NotConnected => Ok(()),
+            r => r
+        });
+        #[cfg(unix)]
+        let closed = sys::close(self.into_raw_fd());
+        #[cfg(not(unix))]
+        let closed = { drop(self); Ok(()) };
+        flushed.and(closed)
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self>{
+        match self{
+            #[cfg(feature = "tcp")]
+            Stream::Inet(stream) => {
+                match stream.try_clone(){
+                    Ok(new_stream) => {
+                        Ok(Self::from(new_stream))
+                    }
+                    Err(err) => {Err(err)}
+                }
+            }
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(stream) => {
+                match stream.try_clone(){
+                    Ok(new_stream) => {
+                        Ok(Self::from(new_stream))
+                    }
+                    Err(err) => {Err(err)}
+                }
+            }
+        }
+    }
+
+    /// Splits the stream into a read half and a write half that can be moved to different
+    /// threads. Each half owns a handle to the same underlying socket.
+    pub fn into_split(self) -> io::Result<(ReadHalf, WriteHalf)> {
+        let r = self.try_clone()?;
+        Ok((ReadHalf(r), WriteHalf(self)))
+    }
+
+    /// Wraps the stream in an `IdleStream`, which can shut it down once no data has flowed for
+    /// `dur`.
+    pub fn with_idle_timeout(self, dur: Duration) -> IdleStream {
+        IdleStream { stream: self, timeout: dur, last_activity: Instant::now() }
+    }
+
+    /// Splits the stream like `into_split()` and wraps both halves in buffers, for line-oriented
+    /// protocols.
+    ///
+    /// The `BufWriter` flushes any buffered data when dropped, but errors are ignored then; call
+    /// `flush()` explicitly to observe them.
+    pub fn into_buffered(self) -> io::Result<(io::BufReader<ReadHalf>, io::BufWriter<WriteHalf>)> {
+        let (r, w) = self.into_split()?;
+        Ok((io::BufReader::new(r), io::BufWriter::new(w)))
+    }
+}
+
+/// Options for `Stream::connect_with()`.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use unisocket::{ConnectOptions,SocketAddr,Stream};
+/// let addr = SocketAddr::inet(([127, 0, 0, 1], 8080));
+/// let stream = Stream::connect_with(&addr, ConnectOptions::new().nodelay(true).timeout(Duration::from_secs(3)));
+/// ```
+#[derive(Debug,Clone,Default)]
+pub struct ConnectOptions {
+    nodelay: Option<bool>,
+    timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>
+}
+
+impl ConnectOptions {
+    /// Creates a new set of options that leaves everything as is, equivalent to a plain
+    /// `connect()`.
+    pub fn new() -> ConnectOptions {
+        ConnectOptions::default()
+    }
+
+    /// For TCP sockets, set `TCP_NODELAY` after connecting. Ignored for other addresses.
+    pub fn nodelay(&mut self, nodelay: bool) -> &mut ConnectOptions {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Connect with `connect_timeout()` instead of blocking indefinitely.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut ConnectOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the read timeout of the stream after connecting.
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut ConnectOptions {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the write timeout of the stream after connecting.
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut ConnectOptions {
+        self.write_timeout = Some(timeout);
+        self
+    }
+}
+
+/// The reading half of a `Stream`, created by `Stream::into_split()`.
+#[derive(Debug)]
+pub struct ReadHalf(Stream);
+
+/// The writing half of a `Stream`, created by `Stream::into_split()`.
+#[derive(Debug)]
+pub struct WriteHalf(Stream);
+
+impl ReadHalf {
+    /// Returns the `Stream` this half reads from.
+    pub fn get_ref(&self) -> &Stream { &self.0 }
+}
+
+impl WriteHalf {
+    /// Returns the `Stream` this half writes to.
+    pub fn get_ref(&self) -> &Stream { &self.0 }
+
+    /// Shuts down the write side of the connection, signalling EOF to the peer.
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.0.shutdown(net::Shutdown::Write)
+    }
+
+    /// Flushes and, if `shutdown` is `true`, shuts down the write side, reporting errors that
+    /// dropping the half would silently ignore.
+    ///
+    /// For the buffered writer returned by `Stream::into_buffered()`, use
+    /// `BufWriter::into_inner()` to flush it and get the `WriteHalf` back first.
+    pub fn finish(mut self, shutdown: bool) -> io::Result<()> {
+        io::Write::flush(&mut self)?;
+        if shutdown {
+            self.shutdown()?;
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for ReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> { self.0.read_vectored(bufs) }
+}
+
+impl io::Write for WriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.write(buf) }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> { self.0.write_vectored(bufs) }
+    fn flush(&mut self) -> io::Result<()> { self.0.flush() }
+}
+
+/// A `Stream` that remembers its peer address, so that looking it up doesn't need a syscall.
+///
+/// `Stream` itself can't hold extra state, so this dereferences to the wrapped `Stream`. The
+/// address is recorded once by `connect()`, `accept()` or `new()` and never refreshed; in
+/// particular UNIX clients that didn't bind their socket stay `SocketAddr::UnnamedUnix`
+/// even if they bind it afterwards.
+#[derive(Debug)]
+pub struct CachedStream {
+    stream: Stream,
+    peer: Option<SocketAddr>
+}
+
+impl CachedStream {
+    /// Wraps an existing `Stream`, looking up its peer address once.
+    pub fn new(stream: Stream) -> CachedStream {
+        let peer = stream.peer_addr().ok();
+        CachedStream { stream, peer }
+    }
+
+    /// Same as `Stream::connect()`, caching the address that was connected to.
+    pub fn connect(s: &SocketAddr) -> io::Result<CachedStream> {
+        let stream = Stream::connect(s)?;
+        Ok(CachedStream { stream, peer: Some(s.clone()) })
+    }
+
+    /// Same as `Listener::accept()`, caching the address of the accepted peer.
+    pub fn accept(l: &Listener) -> io::Result<CachedStream> {
+        let (stream, peer) = l.accept()?;
+        Ok(CachedStream { stream, peer: Some(peer) })
+    }
+
+    /// Returns the peer address recorded when the stream was created, or `None` if it couldn't
+    /// be determined then.
+    pub fn cached_peer_addr(&self) -> Option<&SocketAddr> {
+        self.peer.as_ref()
+    }
+
+    pub fn into_inner(self) -> Stream {
+        self.stream
+    }
+}
+
+impl std::ops::Deref for CachedStream {
+    type Target = Stream;
+
+    fn deref(&self) -> &Stream {
+        &self.stream
+    }
+}
+
+impl std::ops::DerefMut for CachedStream {
+    fn deref_mut(&mut self) -> &mut Stream {
+        &mut self.stream
+    }
+}
+
+impl io::Read for CachedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.stream.read(buf) }
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> { self.stream.read_vectored(bufs) }
+}
+
+impl io::Write for CachedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.stream.write(buf) }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> { self.stream.write_vectored(bufs) }
+    fn flush(&mut self) -> io::Result<()> { self.stream.flush() }
+}
+
+/// A `Stream` that tracks when data last flowed through it, created by
+/// `Stream::with_idle_timeout()`.
+///
+/// Nothing happens on its own; call `check_idle()` periodically, e.g. between reads with a read
+/// timeout set, to shut down connections to peers that went quiet.
+#[derive(Debug)]
+pub struct IdleStream {
+    stream: Stream,
+    timeout: Duration,
+    last_activity: Instant
+}
+
+impl IdleStream {
+    /// Shuts down both directions of the stream if nothing was read or written within the idle
+    /// timeout, returning whether it did.
+    pub fn check_idle(&self) -> io::Result<bool> {
+        if self.last_activity.elapsed() < self.timeout {
+            return Ok(false);
+        }
+        match self.stream.shutdown(net::Shutdown::Both) {
+            // Already shut down, e.g. by an earlier check.
+            Err(ref e) if e.kind() == io::ErrorKind::NotConnected => (),
+            r => r?,
+        }
+        Ok(true)
+    }
+
+    /// Returns the time of the last successful read or write, or of the creation of this wrapper.
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity
+    }
+
+    pub fn get_ref(&self) -> &Stream {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> Stream {
+        self.stream
+    }
+
+    fn touch<T>(&mut self, r: io::Result<T>) -> io::Result<T> {
+        if r.is_ok() {
+            self.last_activity = Instant::now();
+        }
+        r
+    }
+}
+
+impl io::Read for IdleStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let r = self.stream.read(buf);
+        self.touch(r)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        let r = self.stream.read_vectored(bufs);
+        self.touch(r)
+    }
+}
+
+impl io::Write for IdleStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let r = self.stream.write(buf);
+        self.touch(r)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> {
+        let r = self.stream.write_vectored(bufs);
+        self.touch(r)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Credentials of the process on the other end of a UNIX socket, see `Stream::peer_cred()` and
+/// `Datagram::recv_with_cred()`.
+#[cfg(all(unix, feature = "unix"))]
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub struct PeerCred {
+    uid: u32,
+    gid: u32,
+    pid: Option<i32>
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl PeerCred {
+    fn new(uid: u32, gid: u32, pid: Option<i32>) -> PeerCred {
+        PeerCred { uid, gid, pid }
+    }
+
+    pub fn uid(&self) -> u32 { self.uid }
+    pub fn gid(&self) -> u32 { self.gid }
+    pub fn pid(&self) -> Option<i32> { self.pid }
+}
+
+impl io::Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => (&mut (&*s)).read(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => (&mut (&*s)).read(buf)
+        }
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => (&mut (&*s)).read_vectored(bufs),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => (&mut (&*s)).read_vectored(bufs)
+        }
+    }
+}
+
+impl io::Write for &Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => (&mut (&*s)).write(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => (&mut (&*s)).write(buf)
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => (&mut (&*s)).write_vectored(bufs),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => (&mut (&*s)).write_vectored(bufs)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => (&mut (&*s)).flush(),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => (&mut (&*s)).flush()
+        }
+    }
+}
+
+impl io::Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { (&mut &*self).read(buf) }
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> { (&mut &*self).read_vectored(bufs) }
+}
+
+impl io::Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { (&mut &*self).write(buf) }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> { (&mut &*self).write_vectored(bufs) }
+    fn flush(&mut self) -> io::Result<()> { (&mut &*self).flush() }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.as_raw_fd(),
+            #[cfg(feature = "unix")]
+            Stream::Unix(s) => s.as_raw_fd()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for Stream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.as_fd(),
+            #[cfg(feature = "unix")]
+            Stream::Unix(s) => s.as_fd()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl IntoRawFd for Stream {
+    fn into_raw_fd(self) -> RawFd {
+        match self {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => s.into_raw_fd(),
+            #[cfg(feature = "unix")]
+            Stream::Unix(s) => s.into_raw_fd()
+        }
+    }
+}
+
+/// Copies data in both directions between `a` and `b` until both have reached EOF, returning
+/// the number of bytes copied from `a` to `b` and from `b` to `a`.
+///
+/// When one side reaches EOF, the write half of the other is shut down so that the EOF is passed
+/// on, while data keeps flowing in the opposite direction. One direction is copied on a separate
+/// thread. If a direction fails, both streams are shut down completely so that the other
+/// direction stops as well, and the error is returned once both have finished.
+pub fn copy_bidirectional(a: &Stream, b: &Stream) -> io::Result<(u64, u64)> {
+    fn copy_half(from: &Stream, to: &Stream) -> io::Result<u64> {
+        let r = io::copy(&mut &*from, &mut &*to).and_then(|n| match to.shutdown(net::Shutdown::Write) {
+            Err(e) if e.kind() != io::ErrorKind::NotConnected => Err(e),
+            _ => Ok(n),
+        });
+        if r.is_err() {
+            let _ = from.shutdown(net::Shutdown::Both);
+            let _ = to.shutdown(net::Shutdown::Both);
+        }
+        r
+    }
+
+    std::thread::scope(|scope| {
+        let a_to_b = scope.spawn(|| copy_half(a, b));
+        let b_to_a = copy_half(b, a);
+        let a_to_b = a_to_b.join().unwrap_or_else(|p| std::panic::resume_unwind(p));
+        Ok((a_to_b?, b_to_a?))
+    })
+}
+
+
+
+
+#[derive(Debug)]
+pub enum Listener {
+    #[cfg(feature = "tcp")]
+    Inet(net::TcpListener),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(unix::UnixListener)
+}
+
+#[cfg(feature = "tcp")]
+impl From<net::TcpListener> for Listener {
+    fn from(s: net::TcpListener) -> Listener {
+        Listener::Inet(s)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl From<unix::UnixListener> for Listener {
+    fn from(s: unix::UnixListener) -> Listener {
+        Listener::Unix(s)
+    }
+}
+
+impl Listener {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", fields(family = %s.family()), err))]
+    pub fn bind(s: &SocketAddr) -> io::Result<Listener> {
+        match s {
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(s) => net::TcpListener::bind(s).map(Listener::Inet),
+            #[cfg(not(feature = "tcp"))]
+            SocketAddr::Inet(_) => Err(tcp_disabled()),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(s) => unix::UnixListener::bind(s).map(Listener::Unix),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::UnnamedUnix => Err(unnamed_addr()),
+            #[cfg(all(target_os = "linux", feature = "unix"))]
+            SocketAddr::AbstractUnix(n) => {
+                use std::os::linux::net::SocketAddrExt;
+                let a = unix::SocketAddr::from_abstract_name(n)?;
+                unix::UnixListener::bind_addr(&a).map(Listener::Unix)
+            },
+            #[cfg(all(unix, not(feature = "unix")))]
+            _ => Err(unix_disabled())
+        }
+    }
+
+    /// Constructs a `Listener` from a raw file descriptor, such as one passed by systemd socket
+    /// activation, choosing the variant from the address it is known to be bound to.
+    ///
+    /// `addr` is only used to pick the variant and isn't stored: `Listener` is a plain enum of the
+    /// libstd listeners, and giving it an extra field would change its shape. `local_addr()` still
+    /// asks the kernel, which reports the path or abstract name the socket was bound to. Use
+    /// `UnlinkingListener::from_raw_fd_with_addr()` to get a listener that does keep the path, and
+    /// removes the socket file on drop.
+    ///
+    /// Fails with an error of kind `Unsupported`, after closing `fd`, if the feature for the
+    /// family of `addr` (`tcp` or `unix`) is disabled.
+    ///
+    /// # Safety
+    ///
+    /// The `fd` passed in must be an owned file descriptor of a listening socket of the same
+    /// family as `addr`; in particular, it must be open. Ownership is transferred to the returned
+    /// `Listener`.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd_with_addr(fd: RawFd, addr: &SocketAddr) -> io::Result<Listener> {
+        match addr.is_unix() {
+            #[cfg(feature = "unix")]
+            true => Ok(Listener::Unix(unix::UnixListener::from_raw_fd(fd))),
+            #[cfg(not(feature = "unix"))]
+            true => { libc::close(fd); Err(unix_disabled()) },
+            #[cfg(feature = "tcp")]
+            false => Ok(Listener::Inet(net::TcpListener::from_raw_fd(fd))),
+            #[cfg(not(feature = "tcp"))]
+            false => { libc::close(fd); Err(tcp_disabled()) }
+        }
+    }
+
+    /// Same as `bind()`, but takes anything that converts into a `SocketAddr`, such as a
+    /// `std::net::SocketAddr`.
+    pub fn bind_into<A: Into<SocketAddr>>(a: A) -> io::Result<Listener> {
+        Self::bind(&a.into())
+    }
+
+    /// Same as `bind()`, but for UNIX sockets this will try to re-bind to the path if the process
+    /// that used to listen to this address is no longer running. It can also optionally set the
+    /// permissions of the UNIX socket.
+    ///
+    /// This is a shorthand for `bind_with()` with `reuse_stale(true)` and the given mode.
+    ///
+    /// # Limitations
+    ///
+    /// Trying to bind to the same UNIX socket path from multiple processes is subject to a race
+    /// condition.
+    ///
+    /// The permissions are set *after* performing the `bind()` operation, so if the default umask
+    /// is less restrictive than the given mode, there is a short window where an unprivileged
+    /// process could attempt to connect to the socket.
+    pub fn bind_reuse(s: &SocketAddr, mode: Option<u32>) -> io::Result<Listener> {
+        let mut opts = BindOptions::new();
+        opts.reuse_stale(true);
+        opts.mode = mode;
+        Self::bind_with(s, &opts)
+    }
+
+    /// Binds a UNIX socket at a temporary path next to the target, gives it its permissions there
+    /// and then renames it over the target path, so clients never see a missing socket or one
+    /// with the wrong permissions. Other addresses are bound with plain `bind()`.
+    ///
+    /// Like `bind_reuse()`, this fails with `AddrInUse` if the target is a live socket or not a
+    /// socket at all, while a stale socket file is replaced. The rename is atomic when both paths
+    /// are on the same filesystem. The temporary path is `<path>.tmp-<pid>-<n>`, with `n` counting
+    /// the calls in this process; a stale socket left there by a crashed process is removed first,
+    /// and the temporary socket is removed again if anything fails.
+    ///
+    /// # Limitations
+    ///
+    /// Checking the target for a live listener and renaming over it are separate steps, so two
+    /// processes binding the same path at the same time can still both succeed.
+    ///
+    /// The kernel keeps knowing the socket by the temporary path, which no longer exists after
+    /// the rename. The returned `RenamedListener` reports the target path from `local_addr()`,
+    /// but the streams it accepts still report the temporary path from theirs.
+    pub fn bind_atomic(s: &SocketAddr, mode: Option<u32>) -> io::Result<RenamedListener> {
+        match s {
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(p) => {
+                use std::os::unix::fs::FileTypeExt;
+                use std::sync::atomic::{AtomicUsize,Ordering};
+                static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+                let in_use = || io::Error::new(io::ErrorKind::AddrInUse, "Address already in use");
+                match std::fs::symlink_metadata(p) {
+                    Ok(ref m) if !m.file_type().is_socket() => return Err(in_use()),
+                    Ok(_) => match Stream::connect(s) {
+                        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+                        _ => return Err(in_use()),
+                    },
+                    Err(_) => (),
+                }
+
+                let mut tmp = p.clone().into_os_string();
+                tmp.push(format!(".tmp-{}-{}", std::process::id(), SEQ.fetch_add(1, Ordering::Relaxed)));
+                let tmp = SocketAddr::Unix(tmp.into());
+                let l = Self::bind_with(&tmp, BindOptions::new().reuse_stale(true))?;
+                let tmp_path = tmp.as_unix_path().unwrap();
+                let r = match mode {
+                    Some(perm) => Self::set_mode(&tmp, perm),
+                    None => Ok(()),
+                };
+                if let Err(e) = r.and_then(|_| std::fs::rename(tmp_path, p)) {
+                    let _ = std::fs::remove_file(tmp_path);
+                    return Err(e);
+                }
+                Ok(RenamedListener { listener: l, addr: Some(s.clone()) })
+            },
+            _ => { let _ = mode; Self::bind(s).map(|l| RenamedListener { listener: l, addr: None }) }
+        }
+    }
+
+    /// Binds to the given address using the given `BindOptions`.
+    ///
+    /// The limitations documented on `bind_reuse()` apply to the `reuse_stale` and `mode`
+    /// options as well.
+    pub fn bind_with(s: &SocketAddr, opts: &BindOptions) -> io::Result<Listener> {
+        #[cfg(target_os = "linux")]
+        let abstract_addr;
+        #[cfg(target_os = "linux")]
+        let s = match s {
+            SocketAddr::Unix(p) if opts.abstract_namespace => {
+                use std::os::unix::ffi::OsStrExt;
+                abstract_addr = SocketAddr::AbstractUnix(p.as_os_str().as_bytes().to_vec());
+                &abstract_addr
+            },
+            _ => s,
+        };
+
+        let bind = || match s {
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(a) if opts.reuse_port || opts.backlog.is_some()
+                                   || (a.is_ipv6() && opts.v6_only.is_some()) => {
+                Self::bind_tcp_manual(a, opts)
+            },
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(_) if opts.backlog.is_some() => {
+                sys::unix_listener(s, opts.backlog.unwrap()).map(Listener::Unix)
+            },
+            #[cfg(all(target_os = "linux", feature = "unix"))]
+            SocketAddr::AbstractUnix(_) if opts.backlog.is_some() => {
+                sys::unix_listener(s, opts.backlog.unwrap()).map(Listener::Unix)
+            },
+            _ => Self::bind(s),
+        };
+        let b = match bind() {
+            Err(e) if opts.reuse_stale && e.kind() == io::ErrorKind::AddrInUse => Self::rebind_stale(s, e, bind)?,
+            r => r?,
+        };
+
+        if let Some(perm) = opts.mode {
+            Self::set_mode(s, perm)?;
+        }
+        Ok(b)
+    }
+
+    /// Binds a TCP listener with options that need to be set before `bind()`, which libstd has no
+    /// support for.
+    #[cfg(feature = "tcp")]
+    fn bind_tcp_manual(a: &net::SocketAddr, _opts: &BindOptions) -> io::Result<Listener> {
+        #[cfg(unix)]
+        {
+            sys::tcp_listener(a, _opts).map(Listener::Inet)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = a;
+            Err(unsupported("Setting pre-bind socket options"))
+        }
+    }
+
+    /// Removes a UNIX socket that nobody is listening on anymore and binds to it again with
+    /// `bind`. `e` is the error from the initial bind attempt, returned when the socket is still
+    /// alive.
+    fn rebind_stale<F: FnOnce() -> io::Result<Listener>>(s: &SocketAddr, e: io::Error, bind: F) -> io::Result<Listener> {
+        match s {
+            #[cfg(unix)]
+            SocketAddr::Unix(p) => {
+                // Make sure it is a socket in the first place (we don't want to overwrite a
+                // regular file)
+                use std::os::unix::fs::FileTypeExt;
+                match std::fs::symlink_metadata(p) {
+                    Ok(ref m) if m.file_type().is_socket() => (),
+                    _ => return Err(e),
+                };
+
+                // Try to connect to the socket to see if it's still alive.
+                match Stream::connect(s) {
+                    // Not alive, delete the socket and try to bind again.
+                    Err(ref e2) if e2.kind() == io::ErrorKind::ConnectionRefused
+                        => std::fs::remove_file(p).and_then(|_| bind()),
+                    _ => Err(e),
+                }
+            },
+            _ => { let _ = bind; Err(e) }
+        }
+    }
+
+    /// Sets the permissions of a UNIX socket path, does nothing for other addresses.
+    fn set_mode(s: &SocketAddr, _perm: u32) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            if let SocketAddr::Unix(p) = s {
+                use std::fs::{set_permissions,Permissions};
+                use std::os::unix::fs::PermissionsExt;
+                set_permissions(p, Permissions::from_mode(_perm))?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = s;
+        Ok(())
+    }
+
+    /// Takes out the inner `TcpListener`, or gives the listener back for UNIX sockets.
+    #[cfg(feature = "tcp")]
+    pub fn into_tcp(self) -> Result<net::TcpListener, Listener> {
+        match self {
+            Listener::Inet(l) => Ok(l),
+            #[cfg(all(unix, feature = "unix"))]
+            l => Err(l)
+        }
+    }
+
+    /// Takes out the inner `UnixListener`, or gives the listener back for TCP sockets.
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn into_unix(self) -> Result<unix::UnixListener, Listener> {
+        match self {
+            Listener::Unix(l) => Ok(l),
+            #[cfg(feature = "tcp")]
+            l => Err(l)
+        }
+    }
+
+    /// Returns the address this listener is bound to, e.g. to find out which port was picked
+    /// when binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.local_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => l.local_addr().map(|e| e.into())
+        }
+    }
+
+    /// Sets the value for the `IP_TTL` option on this socket.
+    ///
+    /// Returns an error of kind `Unsupported` for UNIX sockets.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.set_ttl(ttl),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(_) => { let _ = ttl; Err(unsupported("IP_TTL")) }
+        }
+    }
+
+    /// Gets the value of the `IP_TTL` option for this socket.
+    ///
+    /// Returns an error of kind `Unsupported` for UNIX sockets.
+    pub fn ttl(&self) -> io::Result<u32> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.ttl(),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(_) => Err(unsupported("IP_TTL"))
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.set_nonblocking(nonblocking),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => l.set_nonblocking(nonblocking)
+        }
+    }
+
+    /// Same as `Stream::set_cloexec()`.
+    #[cfg(unix)]
+    pub fn set_cloexec(&self, cloexec: bool) -> io::Result<()> {
+        sys::set_cloexec(self.as_raw_fd(), cloexec)
+    }
+
+    /// Gets the value of the `SO_ERROR` option on this socket, clearing it in the process.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.take_error(),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => l.take_error()
+        }
+    }
+
+    /// Accepts a new connection, returning the stream and the peer's address.
+    ///
+    /// UNIX clients that didn't bind their socket are reported as `SocketAddr::UnnamedUnix`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), err,
+        fields(local = tracing::field::Empty, family = tracing::field::Empty, peer = tracing::field::Empty)))]
+    pub fn accept(&self) -> io::Result<(Stream,SocketAddr)> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::Span::current();
+        #[cfg(feature = "tracing")]
+        if !span.is_disabled() {
+            if let Ok(local) = self.local_addr() {
+                span.record("family", tracing::field::display(local.family()));
+                span.record("local", tracing::field::debug(local));
+            }
+        }
+        let r: io::Result<(Stream,SocketAddr)> = match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.accept().map(|(s,e)| (s.into(), e.into())),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => l.accept().map(|(s,e)| (s.into(), e.into()))
+        };
+        #[cfg(feature = "tracing")]
+        if let Ok((_, peer)) = &r {
+            span.record("peer", tracing::field::debug(peer));
+            tracing::debug!("accepted connection");
+        }
+        r
+    }
+
+    /// Accepts a new connection like `accept()` and applies `options` to the stream, instead of
+    /// relying on what accepted sockets inherit from the listener, which differs by platform.
+    ///
+    /// If applying an option fails, the connection is dropped and the error returned.
+    pub fn accept_with(&self, options: &AcceptOptions) -> io::Result<(Stream,SocketAddr)> {
+        let (stream, addr) = self.accept()?;
+        options.apply(&stream)?;
+        Ok((stream, addr))
+    }
+
+    /// Accepts a new connection like `accept()`, with the returned stream already in non-blocking
+    /// mode.
+    ///
+    /// On Linux and Android this uses `accept4()` with `SOCK_NONBLOCK` and `SOCK_CLOEXEC`, which
+    /// saves a syscall per connection; elsewhere it is `accept()` followed by
+    /// `set_nonblocking(true)`.
+    pub fn accept_nonblocking(&self) -> io::Result<(Stream,SocketAddr)> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let (fd, addr) = sys::accept_nonblocking(self.as_raw_fd())?;
+            let stream = match self {
+                #[cfg(feature = "tcp")]
+                Listener::Inet(_) => Stream::Inet(fd.into()),
+                #[cfg(feature = "unix")]
+                Listener::Unix(_) => Stream::Unix(fd.into())
+            };
+            Ok((stream, addr))
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let (stream, addr) = self.accept()?;
+            stream.set_nonblocking(true)?;
+            Ok((stream, addr))
+        }
+    }
+
+    /// Waits up to `timeout` for a new connection, returning `Ok(None)` if none arrived.
+    ///
+    /// The listener is switched to non-blocking mode for the actual `accept()`, and restored to
+    /// its previous mode afterwards. Only supported on UNIX-like platforms; elsewhere an error of
+    /// kind `Unsupported` is returned.
+    pub fn accept_timeout(&self, timeout: Duration) -> io::Result<Option<(Stream,SocketAddr)>> {
+        #[cfg(unix)]
+        {
+            let fd = self.as_raw_fd();
+            if !sys::poll_readable(fd, timeout)? {
+                return Ok(None);
+            }
+            let was_nonblocking = sys::is_nonblocking(fd)?;
+            self.set_nonblocking(true)?;
+            let r = self.accept();
+            if !was_nonblocking {
+                self.set_nonblocking(false)?;
+            }
+            match r {
+                Ok((s, a)) => {
+                    // Accepted sockets inherit O_NONBLOCK on some platforms (but not Linux).
+                    if !was_nonblocking {
+                        s.set_nonblocking(false)?;
+                    }
+                    Ok(Some((s, a)))
+                },
+                // Someone else took the connection before us.
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = timeout;
+            Err(unsupported("Accepting with a timeout"))
+        }
+    }
+
+    /// Creates a new independently owned handle to the underlying socket.
+    ///
+    /// The returned `Listener` shares the socket with this one, so connections are distributed
+    /// between concurrent `accept()` calls on either handle.
+    pub fn try_clone(&self) -> io::Result<Listener> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.try_clone().map(Listener::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => l.try_clone().map(Listener::Unix)
+        }
+    }
+
+    /// Returns an iterator over the connections being received on this listener.
+    ///
+    /// The iterator will never return `None` and will also not yield the peer's `SocketAddr`.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+}
+
+/// A `Listener` that removes its UNIX socket file when dropped.
+///
+/// Created by `UnlinkingListener::bind_with()`, and dereferences to the wrapped `Listener`.
+/// Removal is best-effort and errors are ignored. Nothing is removed for inet or abstract UNIX
+/// sockets, nor after the listener has been taken out with `into_inner()`. This does not help
+/// when the process exits without running destructors, use `BindOptions::reuse_stale()` to
+/// deal with the sockets left behind in that case.
+#[derive(Debug)]
+pub struct UnlinkingListener {
+    listener: Option<Listener>,
+    #[cfg(unix)]
+    path: Option<PathBuf>
+}
+
+impl UnlinkingListener {
+    /// Same as `Listener::bind_with()`, but removes the socket file again when dropped.
+    pub fn bind_with(s: &SocketAddr, opts: &BindOptions) -> io::Result<UnlinkingListener> {
+        let listener = Listener::bind_with(s, opts)?;
+        Ok(UnlinkingListener {
+            listener: Some(listener),
+            #[cfg(unix)]
+            path: s.as_unix_path().filter(|_| !opts.is_abstract()).map(Path::to_path_buf)
+        })
+    }
+
+    /// Same as `Listener::from_raw_fd_with_addr()`, but removes the socket file at `addr` again
+    /// when dropped.
+    ///
+    /// # Safety
+    ///
+    /// See `Listener::from_raw_fd_with_addr()`.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd_with_addr(fd: RawFd, addr: &SocketAddr) -> io::Result<UnlinkingListener> {
+        Ok(UnlinkingListener {
+            listener: Some(Listener::from_raw_fd_with_addr(fd, addr)?),
+            path: addr.as_unix_path().map(Path::to_path_buf)
+        })
+    }
+
+    /// Returns the wrapped `Listener`, without removing the socket file.
+    pub fn into_inner(mut self) -> Listener {
+        #[cfg(unix)]
+        {
+            self.path = None;
+        }
+        self.listener.take().unwrap()
+    }
+}
+
+impl std::ops::Deref for UnlinkingListener {
+    type Target = Listener;
+
+    fn deref(&self) -> &Listener {
+        self.listener.as_ref().unwrap()
+    }
+}
+
+impl std::ops::DerefMut for UnlinkingListener {
+    fn deref_mut(&mut self) -> &mut Listener {
+        self.listener.as_mut().unwrap()
+    }
+}
+
+impl Drop for UnlinkingListener {
+    fn drop(&mut self) {
+        // Close the socket before removing its path.
+        drop(self.listener.take());
+        #[cfg(unix)]
+        {
+            if let Some(p) = self.path.take() {
+                let _ = std::fs::remove_file(p);
+            }
+        }
+    }
+}
+
+/// A `Listener` bound by `Listener::bind_atomic()`, which reports the path it was renamed to.
+///
+/// Dereferences to the wrapped `Listener`. The kernel still knows the socket by the temporary
+/// path it was bound at, so `local_addr()` is overridden to return the target address instead;
+/// the wrapped `Listener` and the streams accepted from it keep reporting the temporary path.
+#[derive(Debug)]
+pub struct RenamedListener {
+    listener: Listener,
+    addr: Option<SocketAddr>
+}
+
+impl RenamedListener {
+    /// Returns the address passed to `bind_atomic()` for UNIX sockets, and the address the
+    /// kernel reports for others.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.addr {
+            Some(a) => Ok(a.clone()),
+            None => self.listener.local_addr(),
+        }
+    }
+
+    /// Returns the wrapped `Listener`.
+    pub fn into_inner(self) -> Listener {
+        self.listener
+    }
+}
+
+impl std::ops::Deref for RenamedListener {
+    type Target = Listener;
+
+    fn deref(&self) -> &Listener {
+        &self.listener
+    }
+}
+
+impl std::ops::DerefMut for RenamedListener {
+    fn deref_mut(&mut self) -> &mut Listener {
+        &mut self.listener
+    }
+}
+
+/// A set of `Listener`s accepted from as one, e.g. to serve both a UNIX socket and a TCP port.
+///
+/// The listeners are switched to non-blocking mode, and `accept()` waits until any of them has a
+/// pending connection. Only supported on UNIX-like platforms; elsewhere `accept()` returns an
+/// error of kind `Unsupported`.
+#[derive(Debug)]
+pub struct MultiListener {
+    listeners: Vec<Listener>
+}
+
+impl MultiListener {
+    /// Takes ownership of the listeners, switching them to non-blocking mode.
+    pub fn new(listeners: Vec<Listener>) -> io::Result<MultiListener> {
+        for l in &listeners {
+            l.set_nonblocking(true)?;
+        }
+        Ok(MultiListener { listeners })
+    }
+
+    /// Binds a listener to each of the addresses, as with `Listener::bind()`.
+    pub fn bind(addrs: &[SocketAddr]) -> io::Result<MultiListener> {
+        Self::new(addrs.iter().map(Listener::bind).collect::<io::Result<_>>()?)
+    }
+
+    /// Waits for a connection on any of the listeners and accepts it.
+    ///
+    /// The returned stream is in blocking mode, regardless of whether the platform lets it
+    /// inherit the listener's non-blocking flag. Fails with `InvalidInput` if there are no
+    /// listeners, rather than waiting forever.
+    pub fn accept(&self) -> io::Result<(Stream,SocketAddr)> {
+        if self.listeners.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no listeners to accept from"));
+        }
+        #[cfg(unix)]
+        loop {
+            let fds: Vec<_> = self.listeners.iter().map(|l| l.as_raw_fd()).collect();
+            for i in sys::poll_readable_any(&fds)? {
+                match self.listeners[i].accept() {
+                    Ok((s, addr)) => {
+                        s.set_nonblocking(false)?;
+                        return Ok((s, addr));
+                    },
+                    // Someone else got to the connection first.
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        Err(unsupported("Accepting from multiple listeners"))
+    }
+
+    pub fn listeners(&self) -> &[Listener] {
+        &self.listeners
+    }
+
+    /// Returns the listeners, still in non-blocking mode.
+    pub fn into_inner(self) -> Vec<Listener> {
+        self.listeners
+    }
+}
+
+/// Options for `Listener::bind_with()`.
+///
+/// ```no_run
+/// # #[cfg(unix)] {
+/// # use unisocket::{BindOptions,Listener,SocketAddr};
+/// let addr = SocketAddr::unix("/run/app.sock");
+/// let listener = Listener::bind_with(&addr, BindOptions::new().reuse_stale(true).mode(0o660));
+/// # }
+/// ```
+#[derive(Debug,Clone,Default)]
+pub struct BindOptions {
+    reuse_stale: bool,
+    reuse_port: bool,
+    v6_only: Option<bool>,
+    backlog: Option<u32>,
+    mode: Option<u32>,
+    #[cfg(target_os = "linux")]
+    abstract_namespace: bool
+}
+
+impl BindOptions {
+    /// Creates a new set of options with everything disabled, equivalent to a plain `bind()`.
+    pub fn new() -> BindOptions {
+        BindOptions::default()
+    }
+
+    /// For UNIX sockets, re-bind to the path if the process that used to listen on it is no
+    /// longer running. Ignored for other addresses.
+    pub fn reuse_stale(&mut self, reuse_stale: bool) -> &mut BindOptions {
+        self.reuse_stale = reuse_stale;
+        self
+    }
+
+    /// For TCP sockets, set `SO_REUSEPORT` before binding so that multiple listeners (typically in
+    /// separate processes) can bind to the same port and share incoming connections. Ignored for
+    /// other addresses.
+    ///
+    /// `SO_REUSEADDR` doesn't need an option of its own: like libstd, TCP listeners always set it
+    /// on UNIX-like platforms, so a port can be bound again right away while old connections
+    /// linger in `TIME_WAIT`. Only supported on UNIX-like platforms; elsewhere binding with this
+    /// option fails with an error of kind `Unsupported`.
+    pub fn reuse_port(&mut self, reuse_port: bool) -> &mut BindOptions {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    /// For IPv6 TCP sockets, set `IPV6_V6ONLY` before binding, so that a listener on `[::]`
+    /// accepts IPv4 connections (as IPv4-mapped addresses) only if this is `false`. When unset
+    /// the system default applies. Ignored for other addresses.
+    ///
+    /// Like `reuse_port()`, this is only supported on UNIX-like platforms.
+    pub fn v6_only(&mut self, v6_only: bool) -> &mut BindOptions {
+        self.v6_only = Some(v6_only);
+        self
+    }
+
+    /// Sets the length of the queue of pending connections passed to `listen()`, instead of
+    /// libstd's default (128 on most platforms).
+    ///
+    /// The kernel silently caps the value at `net.core.somaxconn` on Linux (`kern.ipc.somaxconn`
+    /// on macOS and the BSDs). TCP and UNIX listeners are created by hand to apply it, as libstd
+    /// has no way to pass the value to `listen()`. Like `reuse_port()`, this is only supported on
+    /// UNIX-like platforms.
+    pub fn backlog(&mut self, backlog: u32) -> &mut BindOptions {
+        self.backlog = Some(backlog);
+        self
+    }
+
+    /// For UNIX sockets, set the permissions of the socket file after binding. Ignored for other
+    /// addresses.
+    pub fn mode(&mut self, mode: u32) -> &mut BindOptions {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Whether UNIX socket paths are bound in the abstract namespace.
+    #[cfg(unix)]
+    fn is_abstract(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            self.abstract_namespace
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            false
+        }
+    }
+
+    /// Bind UNIX socket paths in the abstract namespace instead of the filesystem, i.e. treat
+    /// 'unix:name' as 'unix:@name'.
+    #[cfg(target_os = "linux")]
+    pub fn abstract_namespace(&mut self, abstract_namespace: bool) -> &mut BindOptions {
+        self.abstract_namespace = abstract_namespace;
+        self
+    }
+}
+
+/// Options for `Listener::accept_with()`, applied to each accepted stream.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use unisocket::{AcceptOptions,Listener,SocketAddr};
+/// let listener = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 8080))).unwrap();
+/// let mut options = AcceptOptions::new();
+/// options.nodelay(true).read_timeout(Duration::from_secs(30));
+/// let (stream, peer) = listener.accept_with(&options).unwrap();
+/// ```
+#[derive(Debug,Clone,Default)]
+pub struct AcceptOptions {
+    nodelay: Option<bool>,
+    nonblocking: Option<bool>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>
+}
+
+impl AcceptOptions {
+    /// Creates a new set of options that leaves everything as is, equivalent to a plain
+    /// `accept()`.
+    pub fn new() -> AcceptOptions {
+        AcceptOptions::default()
+    }
+
+    /// For TCP sockets, set `TCP_NODELAY`. Ignored for UNIX sockets.
+    pub fn nodelay(&mut self, nodelay: bool) -> &mut AcceptOptions {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Set the stream to (non-)blocking mode. Whether an accepted socket inherits the mode of
+    /// the listener depends on the platform, this makes it explicit.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut AcceptOptions {
+        self.nonblocking = Some(nonblocking);
+        self
+    }
+
+    /// Set the read timeout of the stream.
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut AcceptOptions {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the write timeout of the stream.
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut AcceptOptions {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the send buffer size with `Stream::set_send_buffer_size()`.
+    pub fn send_buffer_size(&mut self, size: usize) -> &mut AcceptOptions {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Set the receive buffer size with `Stream::set_recv_buffer_size()`.
+    pub fn recv_buffer_size(&mut self, size: usize) -> &mut AcceptOptions {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    fn apply(&self, stream: &Stream) -> io::Result<()> {
+        if let Some(nodelay) = self.nodelay {
+            stream.set_nodelay(nodelay)?;
+        }
+        if let Some(nonblocking) = self.nonblocking {
+            stream.set_nonblocking(nonblocking)?;
+        }
+        if self.read_timeout.is_some() {
+            stream.set_read_timeout(self.read_timeout)?;
+        }
+        if self.write_timeout.is_some() {
+            stream.set_write_timeout(self.write_timeout)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            stream.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            stream.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+/// An iterator that infinitely `accept`s connections on a `Listener`.
+///
+/// This struct is created by the `Listener::incoming` method.
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a Listener
+}
+
+impl<'a> Iterator for Incoming<'a> {
+    type Item = io::Result<Stream>;
+
+    fn next(&mut self) -> Option<io::Result<Stream>> {
+        Some(self.listener.accept().map(|(s,_)| s))
+    }
+}
+
+impl<'a> IntoIterator for &'a Listener {
+    type Item = io::Result<Stream>;
+    type IntoIter = Incoming<'a>;
+
+    fn into_iter(self) -> Incoming<'a> {
+        self.incoming()
+    }
+}
+
+
+
+
+#[cfg(unix)]
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.as_raw_fd(),
+            #[cfg(feature = "unix")]
+            Listener::Unix(l) => l.as_raw_fd()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.as_fd(),
+            #[cfg(feature = "unix")]
+            Listener::Unix(l) => l.as_fd()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl IntoRawFd for Listener {
+    fn into_raw_fd(self) -> RawFd {
+        match self {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => l.into_raw_fd(),
+            #[cfg(feature = "unix")]
+            Listener::Unix(l) => l.into_raw_fd()
+        }
+    }
+}
+
+
+
+
+#[cfg(all(test, unix, feature = "unix"))]
+fn temp_sock_path(name: &str) -> PathBuf {
+    let p = std::env::temp_dir().join(format!("unisocket-{}-{}.sock", std::process::id(), name));
+    let _ = std::fs::remove_file(&p);
+    p
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_inet() {
+    let ip4 = "127.0.0.1:10".parse::<net::SocketAddr>().unwrap();
+    let ip6 = "[::20]:10".parse::<net::SocketAddr>().unwrap();
+    assert_eq!(ip4.to_string(), "127.0.0.1:10".parse::<SocketAddr>().unwrap().to_string());
+    assert_eq!(ip4.to_string(), SocketAddr::from(ip4).to_string());
+    assert_eq!(ip6.to_string(), "[::20]:10".parse::<SocketAddr>().unwrap().to_string());
+    assert_eq!(ip6.to_string(), SocketAddr::from(ip6).to_string());
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+#[cfg(unix)]
+fn test_socket_addr_unix() {
+    assert_eq!("unix:/tmp/sock".parse::<SocketAddr>().unwrap().to_string(), "unix:/tmp/sock");
+    assert!("/tmp/sock".parse::<SocketAddr>().is_err());
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_parse_errors() {
+    assert!(matches!("not an addr".parse::<SocketAddr>(), Err(ParseSocketAddrError::Inet(_))));
+    #[cfg(unix)]
+    {
+        assert_eq!("unix:".parse::<SocketAddr>(), Err(ParseSocketAddrError::InvalidUnixPath));
+        assert_eq!("unix:a\0b".parse::<SocketAddr>(), Err(ParseSocketAddrError::InvalidUnixPath));
+        assert_eq!("unix:unix:/a".parse::<SocketAddr>().unwrap(), SocketAddr::Unix(PathBuf::from("unix:/a")));
+    }
+    #[cfg(target_os = "linux")]
+    assert_eq!("unix:@".parse::<SocketAddr>(), Err(ParseSocketAddrError::EmptyAbstractName));
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_socket_addr_unix_unnamed() {
+    let (a, _b) = unix::UnixStream::pair().unwrap();
+    let unnamed = SocketAddr::from(a.local_addr().unwrap());
+    assert_eq!(unnamed, SocketAddr::UnnamedUnix);
+    assert_eq!(unnamed.to_string(), "unix:<unnamed>");
+    assert_eq!("unix:<unnamed>".parse::<SocketAddr>().unwrap(), unnamed);
+
+    let literal = "unix:unnamed".parse::<SocketAddr>().unwrap();
+    assert_eq!(literal, SocketAddr::Unix(PathBuf::from("unnamed")));
+    assert_ne!(literal, unnamed);
+    assert_ne!(literal.to_string(), unnamed.to_string());
+
+    assert_eq!(Stream::connect(&unnamed).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(Listener::bind(&unnamed).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_socket_addr_serde() {
+    let mut addrs = vec!["127.0.0.1:8080", "[::1]:8080"];
+    if cfg!(unix) {
+        addrs.push("unix:/run/app.sock");
+    }
+    for a in addrs {
+        let addr = a.parse::<SocketAddr>().unwrap();
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(json, format!("\"{}\"", a));
+        assert_eq!(serde_json::from_str::<SocketAddr>(&json).unwrap(), addr);
+    }
+    assert!(serde_json::from_str::<SocketAddr>("\"not an address\"").is_err());
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_ord() {
+    let mut addrs = vec!["[::1]:80", "127.0.0.1:80", "127.0.0.1:8"];
+    if cfg!(unix) {
+        addrs.extend_from_slice(&["unix:/b", "unix:/a"]);
+    }
+    let set = addrs.iter().map(|a| a.parse::<SocketAddr>().unwrap()).collect::<std::collections::BTreeSet<_>>();
+    let sorted = set.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let mut expected = vec!["127.0.0.1:8", "127.0.0.1:80", "[::1]:80"];
+    if cfg!(unix) {
+        expected.extend_from_slice(&["unix:/a", "unix:/b"]);
+    }
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_constructors() {
+    assert_eq!(SocketAddr::inet(([127, 0, 0, 1], 80)).to_string(), "127.0.0.1:80");
+    assert_eq!(SocketAddr::inet("[::1]:80".parse::<net::SocketAddrV6>().unwrap()).to_string(), "[::1]:80");
+    #[cfg(unix)]
+    {
+        assert_eq!(SocketAddr::unix("/tmp/sock").to_string(), "unix:/tmp/sock");
+        assert_eq!(SocketAddr::unix(PathBuf::from("rel.sock")).to_string(), "unix:rel.sock");
+    }
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_family_predicates() {
+    let ip4 = "127.0.0.1:10".parse::<SocketAddr>().unwrap();
+    assert!(ip4.is_inet() && ip4.is_ipv4() && !ip4.is_ipv6() && !ip4.is_unix());
+    let ip6 = "[::1]:10".parse::<SocketAddr>().unwrap();
+    assert!(ip6.is_inet() && !ip6.is_ipv4() && ip6.is_ipv6() && !ip6.is_unix());
+    #[cfg(unix)]
+    {
+        let u = "unix:/tmp/sock".parse::<SocketAddr>().unwrap();
+        assert!(!u.is_inet() && !u.is_ipv4() && !u.is_ipv6() && u.is_unix());
+    }
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_as_inner() {
+    let ip4 = "127.0.0.1:10".parse::<net::SocketAddr>().unwrap();
+    assert_eq!(SocketAddr::from(ip4).as_inet(), Some(&ip4));
+    #[cfg(unix)]
+    {
+        assert_eq!(SocketAddr::from(ip4).as_unix_path(), None);
+        let u = "unix:/tmp/sock".parse::<SocketAddr>().unwrap();
+        assert_eq!(u.as_inet(), None);
+        assert_eq!(u.as_unix_path(), Some(Path::new("/tmp/sock")));
+    }
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_port_ip() {
+    let a = "127.0.0.1:8080".parse::<SocketAddr>().unwrap();
+    assert_eq!(a.port(), Some(8080));
+    assert_eq!(a.ip(), Some(net::IpAddr::V4(net::Ipv4Addr::LOCALHOST)));
+    #[cfg(unix)]
+    {
+        let u = "unix:/tmp/sock".parse::<SocketAddr>().unwrap();
+        assert_eq!(u.port(), None);
+        assert_eq!(u.ip(), None);
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_nodelay() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let s = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    s.set_nodelay(true).unwrap();
+    assert!(s.nodelay().unwrap());
+    s.set_nodelay(false).unwrap();
+    assert!(!s.nodelay().unwrap());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_ttl_inet() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    l.set_ttl(42).unwrap();
+    assert_eq!(l.ttl().unwrap(), 42);
+
+    let tl = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let s = Stream::connect(&tl.local_addr().unwrap().into()).unwrap();
+    s.set_ttl(17).unwrap();
+    assert_eq!(s.ttl().unwrap(), 17);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_ttl_unix() {
+    let path = temp_sock_path("ttl");
+    let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    assert_eq!(l.set_ttl(42).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(l.ttl().unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+    let (a, _b) = unix::UnixStream::pair().unwrap();
+    let s = Stream::from(a);
+    assert_eq!(s.set_ttl(17).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(s.ttl().unwrap_err().kind(), io::ErrorKind::Unsupported);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_nonblocking_inet() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    l.set_nonblocking(true).unwrap();
+    assert_eq!(l.accept().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    let tl = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut s = Stream::connect(&tl.local_addr().unwrap().into()).unwrap();
+    s.set_nonblocking(true).unwrap();
+    assert_eq!(io::Read::read(&mut s, &mut [0; 8]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_nonblocking_unix() {
+    let path = temp_sock_path("nonblocking");
+    let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    l.set_nonblocking(true).unwrap();
+    assert_eq!(l.accept().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    let (a, _b) = unix::UnixStream::pair().unwrap();
+    let mut s = Stream::from(a);
+    s.set_nonblocking(true).unwrap();
+    assert_eq!(io::Read::read(&mut s, &mut [0; 8]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_raw_fd_roundtrip() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let fd = l.as_raw_fd();
+    let l = unsafe { net::TcpListener::from_raw_fd(l.into_raw_fd()) };
+    assert_eq!(l.as_raw_fd(), fd);
+
+    let s = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    let fd = s.as_raw_fd();
+    let s = unsafe { net::TcpStream::from_raw_fd(s.into_raw_fd()) };
+    assert_eq!(s.as_raw_fd(), fd);
+    assert_eq!(s.peer_addr().unwrap(), l.local_addr().unwrap());
+
+    let (a, _b) = unix::UnixStream::pair().unwrap();
+    let s = Stream::from(a);
+    let fd = s.as_raw_fd();
+    let s = unsafe { unix::UnixStream::from_raw_fd(s.into_raw_fd()) };
+    assert_eq!(s.as_raw_fd(), fd);
+    assert!(s.peer_addr().is_ok());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_from_raw_fd() {
+    let (a, b) = unix::UnixStream::pair().unwrap();
+    let mut a = unsafe { Stream::from_unix_raw_fd(a.into_raw_fd()) };
+    assert!(a.local_addr().unwrap().is_unix());
+    io::Write::write_all(&mut a, b"ping").unwrap();
+    let mut buf = [0; 4];
+    io::Read::read_exact(&mut &b, &mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let c = net::TcpStream::connect(l.local_addr().unwrap()).unwrap();
+    let s = unsafe { Stream::from_tcp_raw_fd(c.into_raw_fd()) };
+    assert_eq!(s.peer_addr().unwrap(), l.local_addr().unwrap().into());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_listener_incoming() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let _c1 = Stream::connect(&addr).unwrap();
+    let _c2 = Stream::connect(&addr).unwrap();
+    let accepted = l.incoming().take(2).collect::<io::Result<Vec<_>>>().unwrap();
+    assert_eq!(accepted.len(), 2);
+    for s in accepted {
+        assert_eq!(s.local_addr().unwrap(), addr);
+    }
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+#[cfg(all(target_os = "linux", feature = "unix"))]
+fn test_socket_addr_abstract() {
+    let name = format!("unisocket-{}-abstract", std::process::id());
+    let addr = format!("unix:@{}", name).parse::<SocketAddr>().unwrap();
+    assert_eq!(addr, SocketAddr::AbstractUnix(name.as_bytes().to_vec()));
+    assert_eq!(addr.to_string(), format!("unix:@{}", name));
+    assert!(addr.is_unix());
+
+    let l = Listener::bind(&addr).unwrap();
+    let mut c = Stream::connect(&addr).unwrap();
+    assert_eq!(c.peer_addr().unwrap(), addr);
+    let (mut s, _) = l.accept().unwrap();
+    assert_eq!(s.local_addr().unwrap(), addr);
+    io::Write::write_all(&mut c, b"hi").unwrap();
+    let mut buf = [0; 2];
+    io::Read::read_exact(&mut s, &mut buf).unwrap();
+    assert_eq!(&buf, b"hi");
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_connect_timeout() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = l.local_addr().unwrap().into();
+    let s = Stream::connect_timeout(&addr, Duration::from_secs(5)).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), addr);
+
+    // A non-routable address. Depending on the network (and any transparent proxy in between)
+    // this may time out, fail outright or even succeed, but it must never block for long.
+    let start = std::time::Instant::now();
+    let addr = SocketAddr::inet(([10, 255, 255, 1], 81));
+    if let Err(e) = Stream::connect_timeout(&addr, Duration::from_millis(200)) {
+        assert_ne!(e.kind(), io::ErrorKind::WouldBlock);
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(feature = "parsing")]
+fn test_stream_connect_str() {
+    // Depending on the resolver, 'localhost' may yield ::1 before 127.0.0.1, in which case the
+    // first attempt is refused and the IPv4 fallback must be tried.
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = l.local_addr().unwrap().port();
+    let s = Stream::connect_str(&format!("localhost:{}", port)).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), l.local_addr().unwrap().into());
+
+    let resolves_v6 = net::ToSocketAddrs::to_socket_addrs("localhost:0").unwrap().any(|a| a.is_ipv6());
+    if let (true, Ok(l6)) = (resolves_v6, net::TcpListener::bind("[::1]:0")) {
+        let port = l6.local_addr().unwrap().port();
+        let s = Stream::connect_str(&format!("localhost:{}", port)).unwrap();
+        assert_eq!(s.peer_addr().unwrap(), l6.local_addr().unwrap().into());
+    }
+
+    assert!(Stream::connect_str("not an address").is_err());
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_connect_str_unix() {
+    let path = temp_sock_path("connect-str");
+    let _l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    let s = Stream::connect_str(&format!("unix:{}", path.display())).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), SocketAddr::Unix(path.clone()));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_peek() {
+    let (a, b) = unix::UnixStream::pair().unwrap();
+    let (mut a, mut b) = (Stream::from(a), Stream::from(b));
+    io::Write::write_all(&mut a, b"HELLO").unwrap();
+    let mut buf = [0; 3];
+    assert_eq!(b.peek(&mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"HEL");
+    let mut buf = [0; 5];
+    io::Read::read_exact(&mut b, &mut buf).unwrap();
+    assert_eq!(&buf, b"HELLO");
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_peer_cred() {
+    let (a, _b) = unix::UnixStream::pair().unwrap();
+    let cred = Stream::from(a).peer_cred().unwrap();
+    assert_eq!(cred.uid(), unsafe { libc::getuid() });
+    assert_eq!(cred.gid(), unsafe { libc::getgid() });
+    #[cfg(target_os = "linux")]
+    assert_eq!(cred.pid(), Some(std::process::id() as i32));
+
+    #[cfg(feature = "tcp")]
+    {
+        let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let s = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+        assert_eq!(s.peer_cred().unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_take_error() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    assert!(l.take_error().unwrap().is_none());
+
+    let tl = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let s = Stream::connect(&tl.local_addr().unwrap().into()).unwrap();
+    assert!(s.take_error().unwrap().is_none());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_listener_try_clone() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let workers = (0..2).map(|_| {
+        let l = l.try_clone().unwrap();
+        std::thread::spawn(move || l.accept().map(|(s,_)| s))
+    }).collect::<Vec<_>>();
+    let _c1 = Stream::connect(&addr).unwrap();
+    let _c2 = Stream::connect(&addr).unwrap();
+    for w in workers {
+        assert_eq!(w.join().unwrap().unwrap().local_addr().unwrap(), addr);
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_keepalive() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let s = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    if cfg!(unix) {
+        s.set_keepalive(Some(Duration::from_secs(30))).unwrap();
+        s.set_keepalive(None).unwrap();
+    }
+
+    #[cfg(all(unix, feature = "unix"))]
+    {
+        let (a, _b) = unix::UnixStream::pair().unwrap();
+        let s = Stream::from(a);
+        assert_eq!(s.set_keepalive(Some(Duration::from_secs(30))).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_bind_with_mode_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_sock_path("bind-mode");
+    let addr = SocketAddr::Unix(path.clone());
+    let l = Listener::bind_with(&addr, BindOptions::new().mode(0o600)).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    drop(l);
+
+    // The socket file is left behind, and mode alone must not clean it up.
+    assert_eq!(Listener::bind_with(&addr, BindOptions::new().mode(0o600)).unwrap_err().kind(),
+               io::ErrorKind::AddrInUse);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_bind_with_reuse_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_sock_path("bind-reuse");
+    let addr = SocketAddr::Unix(path.clone());
+    drop(Listener::bind(&addr).unwrap());
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+
+    let l = Listener::bind_with(&addr, BindOptions::new().reuse_stale(true)).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode(), mode);
+
+    // Still alive, so it must not be replaced.
+    assert_eq!(Listener::bind_with(&addr, BindOptions::new().reuse_stale(true)).unwrap_err().kind(),
+               io::ErrorKind::AddrInUse);
+    drop(l);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "unix"))]
+fn test_bind_with_abstract_namespace() {
+    let name = format!("unisocket-{}-bind-abstract", std::process::id());
+    let addr = SocketAddr::Unix(PathBuf::from(&name));
+    let _l = Listener::bind_with(&addr, BindOptions::new().abstract_namespace(true)).unwrap();
+    assert!(!Path::new(&name).exists());
+    let s = Stream::connect(&SocketAddr::AbstractUnix(name.into_bytes())).unwrap();
+    assert!(s.peer_addr().unwrap().is_unix());
+}
+
+#[test]
+#[cfg(all(unix, feature = "tcp"))]
+fn test_bind_rebind_tcp() {
+    // A backlog makes bind_with() create the socket by hand instead of going through libstd.
+    let mut opts = BindOptions::new();
+    opts.backlog(16);
+    let bind = |a: &SocketAddr| {
+        let l = Listener::bind_with(a, &opts).unwrap();
+        let reuse: libc::c_int = sys::getsockopt(l.as_raw_fd(), libc::SOL_SOCKET, libc::SO_REUSEADDR).unwrap();
+        assert_ne!(reuse, 0);
+        l
+    };
+    let l = bind(&SocketAddr::inet(([127, 0, 0, 1], 0)));
+    let addr = l.local_addr().unwrap();
+    // Leave a connection behind in TIME_WAIT on the listener's side.
+    let c = Stream::connect(&addr).unwrap();
+    let (s, _) = l.accept().unwrap();
+    drop(s);
+    drop(c);
+    drop(l);
+    bind(&addr);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_bind_reuse_port() {
+    let opts = BindOptions::new().reuse_port(true).clone();
+    let l1 = Listener::bind_with(&SocketAddr::inet(([127, 0, 0, 1], 0)), &opts).unwrap();
+    let addr = l1.local_addr().unwrap();
+    let l2 = Listener::bind_with(&addr, &opts).unwrap();
+    assert!(Listener::bind(&addr).is_err());
+    drop(l1);
+    let _c = Stream::connect(&addr).unwrap();
+    assert_eq!(l2.accept().unwrap().0.local_addr().unwrap(), addr);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_listener_bind_atomic() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_sock_path("bind-atomic");
+    let addr = SocketAddr::Unix(path.clone());
+    let old = Listener::bind_atomic(&addr, None).unwrap();
+    // A live socket isn't replaced, a stale one is.
+    assert_eq!(Listener::bind_atomic(&addr, None).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+    drop(old);
+    let l = Listener::bind_atomic(&addr, Some(0o600)).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    // The wrapper reports the target path, while the kernel still knows the temporary one, which
+    // is gone now.
+    assert_eq!(l.local_addr().unwrap(), addr);
+    let tmp = Listener::local_addr(&l).unwrap().as_unix_path().unwrap().to_owned();
+    let prefix = format!("{}.tmp-{}-", path.display(), std::process::id());
+    let seq: usize = tmp.to_str().unwrap().strip_prefix(&prefix).unwrap().parse().unwrap();
+    assert!(!tmp.exists());
+
+    let _c = Stream::connect(&addr).unwrap();
+    assert_eq!(l.accept().unwrap().0.local_addr().unwrap().as_unix_path(), Some(&*tmp));
+    drop(l);
+
+    // A stale temporary socket, as left by a crash between bind and rename, is removed.
+    let next = PathBuf::from(format!("{}{}", prefix, seq + 1));
+    drop(unix::UnixListener::bind(&next).unwrap());
+    let l = Listener::bind_atomic(&addr, None).unwrap().into_inner();
+    assert_eq!(l.local_addr().unwrap().as_unix_path(), Some(&*next));
+    drop(l);
+    std::fs::remove_file(&path).unwrap();
+
+    // Neither is a regular file or a directory, and no temporary socket is left behind.
+    std::fs::write(&path, b"").unwrap();
+    assert_eq!(Listener::bind_atomic(&addr, None).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::create_dir(&path).unwrap();
+    assert_eq!(Listener::bind_atomic(&addr, None).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+    assert!(!Path::new(&format!("{}{}", prefix, seq + 2)).exists());
+    std::fs::remove_dir(&path).unwrap();
+
+    #[cfg(feature = "tcp")]
+    {
+        let l = Listener::bind_atomic(&SocketAddr::inet(([127, 0, 0, 1], 0)), Some(0o600)).unwrap();
+        assert!(l.local_addr().unwrap().is_inet());
+    }
+}
+
+#[test]
+#[cfg(all(unix, feature = "tcp"))]
+fn test_stream_oob() {
+    use std::io::{Read,Write};
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let mut c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (mut s, _) = l.accept().unwrap();
+    let mut buf = [0; 2];
+
+    assert!(!s.oob_inline().unwrap());
+    c.write_all(b"ab").unwrap();
+    c.send_oob(b'!').unwrap();
+    c.write_all(b"cd").unwrap();
+    s.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ab");
+    assert_eq!(s.recv_oob().unwrap(), b'!');
+    // The urgent byte was taken out of the regular stream.
+    s.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"cd");
+
+    s.set_oob_inline(true).unwrap();
+    assert!(s.oob_inline().unwrap());
+    c.send_oob(b'?').unwrap();
+    c.write_all(b"e").unwrap();
+    s.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"?e");
+
+    #[cfg(feature = "unix")]
+    {
+        let (u, _) = Stream::pair().unwrap();
+        assert_eq!(u.send_oob(0).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert_eq!(u.recv_oob().unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert_eq!(u.set_oob_inline(true).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_shutdown_write() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut c = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    let mut s = Stream::from(l.accept().unwrap().0);
+    io::Write::write_all(&mut c, b"request").unwrap();
+    c.shutdown_write().unwrap();
+    let mut buf = Vec::new();
+    io::Read::read_to_end(&mut s, &mut buf).unwrap();
+    assert_eq!(buf, b"request");
+
+    // The read half of the client is still usable.
+    io::Write::write_all(&mut s, b"reply").unwrap();
+    s.shutdown_both().unwrap();
+    buf.clear();
+    io::Read::read_to_end(&mut c, &mut buf).unwrap();
+    assert_eq!(buf, b"reply");
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_into_split() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let c = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    let mut s = Stream::from(l.accept().unwrap().0);
+    let (mut r, mut w) = c.into_split().unwrap();
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        io::Read::read_to_end(&mut r, &mut buf).unwrap();
+        buf
+    });
+    io::Write::write_all(&mut w, b"ping").unwrap();
+    w.shutdown().unwrap();
+
+    let mut buf = Vec::new();
+    io::Read::read_to_end(&mut s, &mut buf).unwrap();
+    assert_eq!(buf, b"ping");
+    io::Write::write_all(&mut s, b"pong").unwrap();
+    drop(s);
+    assert_eq!(reader.join().unwrap(), b"pong");
+}
+
+#[test]
+fn test_stream_pair() {
+    let (mut a, mut b) = Stream::pair().unwrap();
+    let mut buf = [0; 4];
+    io::Write::write_all(&mut a, b"ping").unwrap();
+    io::Read::read_exact(&mut b, &mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    io::Write::write_all(&mut b, b"pong").unwrap();
+    io::Read::read_exact(&mut a, &mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_as_inner() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut s = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    assert_eq!(s.as_tcp().unwrap().peer_addr().unwrap(), l.local_addr().unwrap());
+    assert!(s.as_tcp_mut().is_some());
+    #[cfg(all(unix, feature = "unix"))]
+    {
+        assert!(s.as_unix().is_none());
+        assert!(s.as_unix_mut().is_none());
+        let (mut u, _) = Stream::pair().unwrap();
+        assert!(u.as_tcp().is_none());
+        assert!(u.as_tcp_mut().is_none());
+        assert!(u.as_unix().is_some());
+        assert!(u.as_unix_mut().is_some());
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_stream_linger() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let t = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    let (u, _) = Stream::pair().unwrap();
+    for s in &[t, u] {
+        assert_eq!(s.linger().unwrap(), None);
+        s.set_linger(Some(Duration::from_secs(0))).unwrap();
+        assert_eq!(s.linger().unwrap(), Some(Duration::from_secs(0)));
+        s.set_linger(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(s.linger().unwrap(), Some(Duration::from_secs(5)));
+        s.set_linger(None).unwrap();
+        assert_eq!(s.linger().unwrap(), None);
+    }
+}
+
+#[test]
+fn test_stream_timeouts() {
+    let (s, _) = Stream::pair().unwrap();
+    assert_eq!(s.read_timeout().unwrap(), None);
+    s.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    assert_eq!(s.read_timeout().unwrap(), Some(Duration::from_secs(2)));
+    s.set_read_timeout(None).unwrap();
+    assert_eq!(s.read_timeout().unwrap(), None);
+
+    assert_eq!(s.write_timeout().unwrap(), None);
+    s.set_write_timeout(Some(Duration::from_secs(3))).unwrap();
+    assert_eq!(s.write_timeout().unwrap(), Some(Duration::from_secs(3)));
+    s.set_write_timeout(None).unwrap();
+    assert_eq!(s.write_timeout().unwrap(), None);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_stream_buffer_sizes() {
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let t = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    let (u, _) = Stream::pair().unwrap();
+    for s in &[t, u] {
+        s.set_send_buffer_size(64 * 1024).unwrap();
+        s.set_recv_buffer_size(32 * 1024).unwrap();
+        if cfg!(target_os = "linux") {
+            assert!(s.send_buffer_size().unwrap() >= 64 * 1024);
+            assert!(s.recv_buffer_size().unwrap() >= 32 * 1024);
+        } else {
+            assert!(s.send_buffer_size().unwrap() > 0);
+            assert!(s.recv_buffer_size().unwrap() > 0);
+        }
+    }
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_fd_passing() {
+    let (a, b) = Stream::pair().unwrap();
+    let (r, w) = {
+        let mut p = [0; 2];
+        assert_eq!(unsafe { libc::pipe(p.as_mut_ptr()) }, 0);
+        unsafe { (std::fs::File::from_raw_fd(p[0]), std::fs::File::from_raw_fd(p[1])) }
+    };
+    assert_eq!(a.send_with_fds(b"r", &[r.as_raw_fd()]).unwrap(), 1);
+    drop(r);
+
+    let mut buf = [0; 4];
+    let mut fds = [-1; 2];
+    assert_eq!(b.recv_with_fds(&mut buf, &mut fds).unwrap(), (1, 1));
+    assert_eq!(buf[0], b'r');
+    let mut received = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+    io::Write::write_all(&mut &w, b"through the pipe").unwrap();
+    drop(w);
+    let mut out = String::new();
+    io::Read::read_to_string(&mut received, &mut out).unwrap();
+    assert_eq!(out, "through the pipe");
+
+    // Sending more descriptors than the receiver has room for is reported, not silently cut short.
+    let fds3 = [received.as_raw_fd(); 3];
+    assert_eq!(a.send_with_fds(b"3", &fds3).unwrap(), 1);
+    let mut one = [-1; 1];
+    assert_eq!(b.recv_with_fds(&mut buf, &mut one).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(a.send_with_fds(b"3", &fds3).unwrap(), 1);
+    assert_eq!(b.recv_with_fds(&mut buf, &mut []).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    #[cfg(feature = "tcp")]
+    {
+        let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let t = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+        assert_eq!(t.send_with_fds(b"x", &[0]).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_as_fd() {
+    fn sock_family(fd: BorrowedFd<'_>) -> libc::c_int {
+        let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        let r = unsafe {
+            libc::getsockname(fd.as_raw_fd(), &mut storage as *mut _ as *mut libc::sockaddr, &mut len)
+        };
+        assert_eq!(r, 0);
+        storage.ss_family as libc::c_int
+    }
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    assert_eq!(sock_family(l.as_fd()), libc::AF_INET);
+    #[cfg(feature = "unix")]
+    {
+        let (s, _) = Stream::pair().unwrap();
+        assert_eq!(sock_family(s.as_fd()), libc::AF_UNIX);
+    }
+}
+
+#[test]
+fn test_stream_read_exact_timeout() {
+    let (a, mut b) = Stream::pair().unwrap();
+    a.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+    let writer = std::thread::spawn(move || {
+        for chunk in [b"he", b"ll"].iter() {
+            io::Write::write_all(&mut b, &chunk[..]).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        b
+    });
+    let mut buf = [0; 4];
+    a.read_exact_timeout(&mut buf, Duration::from_secs(5)).unwrap();
+    assert_eq!(&buf, b"hell");
+    assert_eq!(a.read_timeout().unwrap(), Some(Duration::from_secs(30)));
+
+    // Only one more byte arrives, so this must time out.
+    let mut b = writer.join().unwrap();
+    io::Write::write_all(&mut b, b"o").unwrap();
+    let start = Instant::now();
+    let err = a.read_exact_timeout(&mut buf, Duration::from_millis(100)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(a.read_timeout().unwrap(), Some(Duration::from_secs(30)));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_listener_local_addr() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    assert!(addr.is_ipv4());
+    assert_ne!(addr.port(), Some(0));
+
+    #[cfg(all(unix, feature = "unix"))]
+    {
+        let path = temp_sock_path("local-addr");
+        let l = Listener::bind(&SocketAddr::unix(&path)).unwrap();
+        assert_eq!(l.local_addr().unwrap(), SocketAddr::unix(&path));
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_unlinking_listener() {
+    let path = temp_sock_path("unlink");
+    let l = UnlinkingListener::bind_with(&SocketAddr::unix(&path), &BindOptions::new()).unwrap();
+    assert!(path.exists());
+    assert_eq!(l.local_addr().unwrap(), SocketAddr::unix(&path));
+    drop(l);
+    assert!(!path.exists());
+
+    let l = UnlinkingListener::bind_with(&SocketAddr::unix(&path), &BindOptions::new()).unwrap();
+    drop(l.into_inner());
+    assert!(path.exists());
+    std::fs::remove_file(&path).unwrap();
+
+    #[cfg(feature = "tcp")]
+    {
+        let l = UnlinkingListener::bind_with(&SocketAddr::inet(([127, 0, 0, 1], 0)), &BindOptions::new()).unwrap();
+        assert!(l.local_addr().unwrap().is_inet());
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(target_os = "linux")]
+fn test_stream_set_mark() {
+    #[cfg(feature = "unix")]
+    {
+        let (u, _) = Stream::pair().unwrap();
+        assert_eq!(u.set_mark(42).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    // SO_MARK needs CAP_NET_ADMIN, skip the rest when we don't have it.
+    let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let s = Stream::connect(&l.local_addr().unwrap().into()).unwrap();
+    if unsafe { libc::getuid() } != 0 {
+        return;
+    }
+    match s.set_mark(42) {
+        Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => return,
+        r => r.unwrap(),
+    }
+    let mark: u32 = sys::getsockopt(s.as_raw_fd(), libc::SOL_SOCKET, libc::SO_MARK).unwrap();
+    assert_eq!(mark, 42);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_into_buffered() {
+    use std::io::{BufRead,Write};
+
+    let (a, b) = Stream::pair().unwrap();
+    let t = std::thread::spawn(move || {
+        let (mut r, mut w) = b.into_buffered().unwrap();
+        let mut line = String::new();
+        while r.read_line(&mut line).unwrap() > 0 {
+            write!(w, "echo {}", line).unwrap();
+            line.clear();
+        }
+        // `w` is flushed on drop.
+    });
+    let (r, mut w) = a.into_buffered().unwrap();
+    writeln!(w, "one").unwrap();
+    writeln!(w, "two").unwrap();
+    w.flush().unwrap();
+    w.get_ref().shutdown().unwrap();
+    let lines: Vec<String> = r.lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines, ["echo one", "echo two"]);
+    t.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_connect_first() {
+    let refused = {
+        let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        SocketAddr::from(l.local_addr().unwrap())
+    };
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let good = l.local_addr().unwrap();
+
+    let s = Stream::connect_first(&[refused.clone(), good.clone()], None).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), good);
+    let s = Stream::connect_first(&[refused.clone(), good.clone()], Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), good);
+
+    let e = Stream::connect_first(&[refused.clone(), refused.clone()], None).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    #[cfg(feature = "parsing")]
+    assert_eq!(e.to_string().matches(&refused.to_string()).count(), 2);
+    assert_eq!(Stream::connect_first(&[], None).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_connect_first_unix_fallback() {
+    let missing = SocketAddr::Unix(temp_sock_path("connect-first-missing"));
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let s = Stream::connect_first(&[missing, l.local_addr().unwrap()], None).unwrap();
+    assert!(s.peer_addr().unwrap().is_inet());
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_listener_accept_unnamed_peer() {
+    let path = temp_sock_path("accept-unnamed");
+    let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    let _c = Stream::connect(&SocketAddr::Unix(path.clone())).unwrap();
+    let (_s, peer) = l.accept().unwrap();
+    assert_eq!(peer, SocketAddr::UnnamedUnix);
+    assert!(peer.is_unnamed());
+    assert!(!SocketAddr::unix("unnamed").is_unnamed());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_connect_with() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let s = Stream::connect_with(&addr, ConnectOptions::new().nodelay(true)
+        .timeout(Duration::from_secs(5)).read_timeout(Duration::from_secs(2))).unwrap();
+    assert!(s.nodelay().unwrap());
+    assert_eq!(s.read_timeout().unwrap(), Some(Duration::from_secs(2)));
+    assert_eq!(s.write_timeout().unwrap(), None);
+
+    let s = Stream::connect_with(&addr, &ConnectOptions::new()).unwrap();
+    assert!(!s.nodelay().unwrap());
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_connect_with_unix() {
+    let path = temp_sock_path("connect-with");
+    let _l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    let s = Stream::connect_with(&SocketAddr::Unix(path.clone()), ConnectOptions::new().nodelay(true)
+        .write_timeout(Duration::from_secs(2))).unwrap();
+    assert_eq!(s.write_timeout().unwrap(), Some(Duration::from_secs(2)));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_listener_into_iter() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let _c = Stream::connect(&addr).unwrap();
+    let mut accepted = 0;
+    for s in &l {
+        assert_eq!(s.unwrap().local_addr().unwrap(), addr);
+        accepted += 1;
+        if accepted == 1 {
+            break;
+        }
+    }
+    assert_eq!(accepted, 1);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_socket_addr_relative_unix() {
+    let name = format!("unisocket-{}-relative.sock", std::process::id());
+    let relative = SocketAddr::unix(&name);
+    assert!(relative.is_relative_unix());
+    assert_eq!(SocketAddr::unix_absolute(&name).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    let absolute = SocketAddr::Unix(temp_sock_path("absolute"));
+    assert!(!absolute.is_relative_unix());
+    assert_eq!(SocketAddr::unix_absolute(absolute.as_unix_path().unwrap()).unwrap(), absolute);
+    assert!(!SocketAddr::UnnamedUnix.is_relative_unix());
+    assert!(!SocketAddr::inet(([127, 0, 0, 1], 1)).is_relative_unix());
+
+    // Relative paths are bound relative to the current directory.
+    let l = Listener::bind(&relative).unwrap();
+    let full = std::env::current_dir().unwrap().join(&name);
+    assert!(full.exists());
+    drop(l);
+    std::fs::remove_file(full).unwrap();
+}
+
+#[test]
+fn test_socket_addr_family() {
+    let v4 = SocketAddr::inet(([127, 0, 0, 1], 80));
+    let v6 = SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 80));
+    assert_eq!(v4.family(), Family::V4);
+    assert_eq!(v6.family(), Family::V6);
+    assert_eq!(Family::V4.to_string(), "ipv4");
+    assert_eq!(Family::V6.to_string(), "ipv6");
+    #[cfg(unix)]
+    {
+        assert_eq!(SocketAddr::unix("/tmp/a.sock").family(), Family::Unix);
+        assert_eq!(SocketAddr::UnnamedUnix.family(), Family::Unix);
+        assert_eq!(Family::Unix.to_string(), "unix");
+    }
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_try_from() {
+    use std::convert::TryFrom;
+
+    let addr = SocketAddr::try_from("127.0.0.1:80").unwrap();
+    assert_eq!(addr, "127.0.0.1:80".parse().unwrap());
+    assert_eq!(SocketAddr::try_from(String::from("127.0.0.1:80")).unwrap(), addr);
+    assert!(matches!(SocketAddr::try_from("nope"), Err(ParseSocketAddrError::Inet(_))));
+    #[cfg(unix)]
+    {
+        assert_eq!(SocketAddr::try_from(String::from("unix:/a")).unwrap(), SocketAddr::unix("/a"));
+        assert_eq!(SocketAddr::try_from(String::from("unix:")), Err(ParseSocketAddrError::InvalidUnixPath));
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_read_to_end_hinted() {
+    use std::io::Write;
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let mut c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (s, _) = l.accept().unwrap();
+    let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let t = std::thread::spawn({
+        let data = data.clone();
+        move || {
+            c.write_all(&data).unwrap();
+            c.shutdown_write().unwrap();
+        }
+    });
+    let mut buf = b"prefix".to_vec();
+    assert_eq!(s.read_to_end_hinted(&mut buf).unwrap(), data.len());
+    assert_eq!(&buf[..6], b"prefix");
+    assert!(buf[6..] == data[..]);
+    t.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_cached_stream_peer_addr() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let c = CachedStream::connect(&addr).unwrap();
+    assert_eq!(c.cached_peer_addr(), Some(&c.peer_addr().unwrap()));
+    let s = CachedStream::accept(&l).unwrap();
+    assert_eq!(s.cached_peer_addr(), Some(&s.peer_addr().unwrap()));
+    assert_eq!(s.cached_peer_addr(), Some(&c.local_addr().unwrap()));
+
+    let c = CachedStream::new(c.into_inner());
+    assert_eq!(c.cached_peer_addr(), Some(&addr));
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_cached_stream_unix_unnamed() {
+    let path = temp_sock_path("cached-unnamed");
+    let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    let c = CachedStream::connect(&SocketAddr::Unix(path.clone())).unwrap();
+    assert_eq!(c.cached_peer_addr(), Some(&c.peer_addr().unwrap()));
+    let s = CachedStream::accept(&l).unwrap();
+    assert_eq!(s.cached_peer_addr(), Some(&SocketAddr::UnnamedUnix));
+    assert_eq!(s.cached_peer_addr(), Some(&s.peer_addr().unwrap()));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_connect_bind_into() {
+    let l = Listener::bind_into(net::SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let addr = *l.local_addr().unwrap().as_inet().unwrap();
+    let s = Stream::connect_into(addr).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), SocketAddr::Inet(addr));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_listener_bind_v6_only() {
+    if net::TcpListener::bind("[::1]:0").is_err() {
+        return; // no IPv6 support
+    }
+    let l = Listener::bind_with(&SocketAddr::inet((net::Ipv6Addr::UNSPECIFIED, 0)), BindOptions::new().v6_only(true)).unwrap();
+    let port = l.local_addr().unwrap().port().unwrap();
+    Stream::connect(&SocketAddr::inet(([0u16, 0, 0, 0, 0, 0, 0, 1], port))).unwrap();
+    assert!(Stream::connect(&SocketAddr::inet(([127, 0, 0, 1], port))).is_err());
+
+    let l = Listener::bind_with(&SocketAddr::inet((net::Ipv6Addr::UNSPECIFIED, 0)), BindOptions::new().v6_only(false)).unwrap();
+    let port = l.local_addr().unwrap().port().unwrap();
+    Stream::connect(&SocketAddr::inet(([127, 0, 0, 1], port))).unwrap();
+
+    // No-op for IPv4.
+    Listener::bind_with(&SocketAddr::inet(([127, 0, 0, 1], 0)), BindOptions::new().v6_only(true)).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_idle_stream() {
+    use std::io::{Read,Write};
+
+    let (a, mut b) = Stream::pair().unwrap();
+    let mut a = a.with_idle_timeout(Duration::from_millis(300));
+    assert!(!a.check_idle().unwrap());
+    std::thread::sleep(Duration::from_millis(150));
+    a.write_all(b"x").unwrap();
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(!a.check_idle().unwrap());
+
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(a.check_idle().unwrap());
+    assert!(a.check_idle().unwrap());
+    let mut buf = Vec::new();
+    b.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"x");
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+#[cfg(unix)]
+fn test_socket_addr_trailing_slash() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash,Hasher};
+    fn hash(a: &SocketAddr) -> u64 {
+        let mut h = DefaultHasher::new();
+        a.hash(&mut h);
+        h.finish()
+    }
+
+    let plain = "unix:/run/app.sock".parse::<SocketAddr>().unwrap();
+    let slash = "unix:/run/app.sock//".parse::<SocketAddr>().unwrap();
+    assert_eq!(plain, slash);
+    assert_eq!(hash(&plain), hash(&slash));
+    assert_eq!(slash.to_string(), "unix:/run/app.sock");
+    assert_eq!(SocketAddr::unix("/run/app.sock/"), plain);
+    assert_eq!(SocketAddr::unix("/run/app.sock/").to_string(), "unix:/run/app.sock");
+
+    assert_eq!("unix:/".parse::<SocketAddr>().unwrap().to_string(), "unix:/");
+    assert_eq!("unix:a.sock/".parse::<SocketAddr>().unwrap().to_string(), "unix:a.sock");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_write_all_vectored() {
+    use std::io::Read;
+
+    let (a, mut b) = Stream::pair().unwrap();
+    // A tiny send buffer makes the kernel take the data in several pieces.
+    a.set_send_buffer_size(4096).unwrap();
+    let bufs: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 64 * 1024]).collect();
+    let t = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        let mut chunk = [0; 1000];
+        loop {
+            match b.read(&mut chunk).unwrap() {
+                0 => return received,
+                n => received.extend_from_slice(&chunk[..n]),
+            }
+        }
+    });
+    let mut slices: Vec<io::IoSlice> = bufs.iter().map(|b| io::IoSlice::new(b)).collect();
+    a.write_all_vectored(&mut slices).unwrap();
+    a.write_all_vectored(&mut []).unwrap();
+    drop(a);
+    assert!(t.join().unwrap() == bufs.concat());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_listener_accept_timeout() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let start = Instant::now();
+    assert!(l.accept_timeout(Duration::from_millis(50)).unwrap().is_none());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (s, peer) = l.accept_timeout(Duration::from_secs(5)).unwrap().unwrap();
+    assert_eq!(peer, c.local_addr().unwrap());
+    assert!(!sys::is_nonblocking(l.as_raw_fd()).unwrap());
+    assert!(!sys::is_nonblocking(s.as_raw_fd()).unwrap());
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_from_url() {
+    assert_eq!(SocketAddr::from_url("tcp://127.0.0.1:8080").unwrap(), "127.0.0.1:8080".parse().unwrap());
+    assert_eq!(SocketAddr::from_url("tcp://[::1]:80/").unwrap(), "[::1]:80".parse().unwrap());
+    assert!(matches!(SocketAddr::from_url("tcp://localhost:80"), Err(ParseSocketAddrError::Inet(_))));
+    assert_eq!(SocketAddr::from_url("http://127.0.0.1:80"), Err(ParseSocketAddrError::UnknownScheme));
+    assert_eq!(SocketAddr::from_url("127.0.0.1:80"), Err(ParseSocketAddrError::UnknownScheme));
+    #[cfg(unix)]
+    {
+        assert_eq!(SocketAddr::from_url("unix:///run/app.sock").unwrap(), SocketAddr::unix("/run/app.sock"));
+        assert_eq!(SocketAddr::from_url("unix://app.sock").unwrap(), SocketAddr::unix("app.sock"));
+        assert_eq!(SocketAddr::from_url("unix:/run/app.sock").unwrap(), SocketAddr::unix("/run/app.sock"));
+        assert_eq!(SocketAddr::from_url("unix://"), Err(ParseSocketAddrError::InvalidUnixPath));
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(target_os = "linux")]
+fn test_stream_tos() {
+    // DSCP AF41, shifted past the two ECN bits.
+    let tos = 34 << 2;
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let s = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    s.set_tos(tos).unwrap();
+    assert_eq!(s.tos().unwrap(), tos);
+
+    if let Ok(l) = Listener::bind(&SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 0))) {
+        let s = Stream::connect(&l.local_addr().unwrap()).unwrap();
+        s.set_tos(tos).unwrap();
+        assert_eq!(s.tos().unwrap(), tos);
+    }
+
+    #[cfg(feature = "unix")]
+    {
+        let (u, _) = Stream::pair().unwrap();
+        assert_eq!(u.set_tos(tos).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_stream_sockopt_raw() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let s = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let get = |s: &Stream| {
+        let mut buf = [0u8; 4];
+        assert_eq!(s.get_sockopt_raw(libc::SOL_SOCKET, libc::SO_REUSEADDR, &mut buf).unwrap(), 4);
+        i32::from_ne_bytes(buf)
+    };
+    s.set_sockopt_raw(libc::SOL_SOCKET, libc::SO_REUSEADDR, &1i32.to_ne_bytes()).unwrap();
+    assert_ne!(get(&s), 0);
+    s.set_sockopt_raw(libc::SOL_SOCKET, libc::SO_REUSEADDR, &0i32.to_ne_bytes()).unwrap();
+    assert_eq!(get(&s), 0);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_connect_retry() {
+    let addr = {
+        let l = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        SocketAddr::from(l.local_addr().unwrap())
+    };
+    let e = Stream::connect_retry(&addr, 2, Duration::from_millis(1)).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+
+    let t = std::thread::spawn({
+        let addr = addr.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let l = Listener::bind(&addr).unwrap();
+            l.accept().unwrap();
+        }
+    });
+    let s = Stream::connect_retry(&addr, 10, Duration::from_millis(10)).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), addr);
+    t.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_listener_from_raw_fd_with_addr() {
+    // Simulate socket activation by handing over the fd of an already bound listener.
+    let path = temp_sock_path("activated");
+    let addr = SocketAddr::unix(&path);
+    let fd = Listener::bind(&addr).unwrap().into_raw_fd();
+    let l = unsafe { Listener::from_raw_fd_with_addr(fd, &addr) }.unwrap();
+    assert!(matches!(l, Listener::Unix(_)));
+    assert_eq!(l.local_addr().unwrap(), addr);
+    let _c = Stream::connect(&addr).unwrap();
+    l.accept().unwrap();
+
+    let l = unsafe { UnlinkingListener::from_raw_fd_with_addr(l.into_raw_fd(), &addr) }.unwrap();
+    assert_eq!(l.local_addr().unwrap(), addr);
+    drop(l);
+    assert!(!path.exists());
+
+    let inet = SocketAddr::inet(([127, 0, 0, 1], 0));
+    let fd = Listener::bind(&inet).unwrap().into_raw_fd();
+    let l = unsafe { Listener::from_raw_fd_with_addr(fd, &inet) }.unwrap();
+    assert!(l.local_addr().unwrap().is_inet());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_inspect() {
+    use std::io::{Read,Write};
+
+    let (mut a, b) = Stream::pair().unwrap();
+    a.write_all(b"GET /").unwrap();
+    let mut buf = [0; 3];
+    let (n, cred) = b.inspect(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"GET");
+    let cred = cred.unwrap();
+    assert_eq!(cred.uid(), unsafe { libc::getuid() });
+    assert_eq!(cred.gid(), unsafe { libc::getgid() });
+    #[cfg(target_os = "linux")]
+    assert_eq!(cred.pid(), Some(std::process::id() as i32));
+
+    // Peeking leaves the data in place.
+    let mut all = [0; 5];
+    (&b).read_exact(&mut all).unwrap();
+    assert_eq!(&all, b"GET /");
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    (&c).write_all(b"x").unwrap();
+    let (s, _) = l.accept().unwrap();
+    assert_eq!(s.inspect(&mut buf).unwrap(), (1, None));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(not(feature = "parsing"))]
+fn test_core_without_parsing() {
+    use std::io::{Read,Write};
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let mut c = Stream::connect(&addr).unwrap();
+    let (mut s, peer) = l.accept().unwrap();
+    assert_eq!(peer, c.local_addr().unwrap());
+    c.write_all(b"ping").unwrap();
+    let mut buf = [0; 4];
+    s.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    assert!(format!("{:?}", addr).starts_with("Inet("));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_is_connected() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (s, _) = l.accept().unwrap();
+    assert!(c.is_connected());
+    assert!(s.is_connected());
+
+    drop(s);
+    // Wait for the FIN to arrive.
+    let mut buf = [0; 1];
+    c.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(c.peek(&mut buf).unwrap(), 0);
+    assert!(!c.is_connected());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_is_connected_unix() {
+    use std::io::{Read,Write};
+
+    let (mut a, b) = Stream::pair().unwrap();
+    (&b).write_all(b"x").unwrap();
+    drop(b);
+    // Unread data keeps the connection looking alive until it is consumed.
+    assert!(a.is_connected());
+    let mut buf = [0; 1];
+    a.read_exact(&mut buf).unwrap();
+    assert!(!a.is_connected());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_socket_addr_to_socket_addrs() {
+    fn first<A: net::ToSocketAddrs>(a: A) -> io::Result<Option<net::SocketAddr>> {
+        a.to_socket_addrs().map(|mut i| i.next())
+    }
+
+    let l = net::TcpListener::bind(SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = SocketAddr::from(l.local_addr().unwrap());
+    assert_eq!(first(&addr).unwrap(), addr.as_inet().copied());
+    net::TcpStream::connect(&addr).unwrap();
+    #[cfg(all(unix, feature = "unix"))]
+    assert_eq!(first(SocketAddr::unix("/tmp/x.sock")).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_write_half_finish() {
+    use std::io::{Read,Write};
+
+    let (a, mut b) = Stream::pair().unwrap();
+    let (_r, mut w) = a.into_buffered().unwrap();
+    w.write_all(b"done").unwrap();
+    w.into_inner().unwrap().finish(true).unwrap();
+    let mut all = Vec::new();
+    b.read_to_end(&mut all).unwrap();
+    assert_eq!(all, b"done");
+
+    // Without shutting down, the peer gets the data but no EOF while the read half is alive.
+    let (a, mut b) = Stream::pair().unwrap();
+    let (_r, mut w) = a.into_buffered().unwrap();
+    w.write_all(b"more").unwrap();
+    w.into_inner().unwrap().finish(false).unwrap();
+    let mut buf = [0; 4];
+    b.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"more");
+    b.set_nonblocking(true).unwrap();
+    assert_eq!(b.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+}
+
+#[test]
+fn test_socket_addr_inet_from_host() {
+    assert_eq!(SocketAddr::inet_from_host("127.0.0.1", 80).unwrap(), SocketAddr::inet(([127, 0, 0, 1], 80)));
+    assert_eq!(SocketAddr::inet_from_host("::1", 443).unwrap(), SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 443)));
+
+    let addr = SocketAddr::inet_from_host("localhost", 8080).unwrap();
+    match addr {
+        SocketAddr::Inet(a) => {
+            assert!(a.ip().is_loopback());
+            assert_eq!(a.port(), 8080);
+        },
+        #[cfg(unix)]
+        _ => panic!("unexpected address {:?}", addr)
+    }
+    assert!(SocketAddr::inet_from_host("/tmp/not-a-host.sock", 1).is_err());
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_stream_close() {
+    use std::io::{Read,Write};
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let mut c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (mut s, _) = l.accept().unwrap();
+    c.write_all(b"bye").unwrap();
+    c.close().unwrap();
+    let mut buf = Vec::new();
+    s.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"bye");
+    // Closing after the peer has gone away is fine too.
+    s.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_send_recv_flags() {
+    let (a, b) = Stream::pair().unwrap();
+    let mut buf = [0; 8];
+    // Nothing has been sent yet, so a non-blocking receive on the blocking socket can't wait.
+    assert_eq!(b.recv_flags(&mut buf, libc::MSG_DONTWAIT).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(a.send_flags(b"flags", libc::MSG_DONTWAIT).unwrap(), 5);
+    assert_eq!(b.recv_flags(&mut buf, libc::MSG_PEEK).unwrap(), 5);
+    assert_eq!(b.recv_flags(&mut buf, 0).unwrap(), 5);
+    assert_eq!(&buf[..5], b"flags");
+}
+
+#[test]
+fn test_socket_addr_normalized() {
+    let mapped = SocketAddr::inet((net::Ipv4Addr::LOCALHOST.to_ipv6_mapped(), 80));
+    let v4 = SocketAddr::inet(([127, 0, 0, 1], 80));
+    assert_ne!(mapped, v4);
+    assert_eq!(mapped.normalized(), v4);
+    assert_eq!(v4.normalized(), v4);
+
+    let v6 = SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 80));
+    assert_eq!(v6.normalized(), v6);
+    #[cfg(unix)]
+    assert_eq!(SocketAddr::unix("/tmp/x.sock").normalized(), SocketAddr::unix("/tmp/x.sock"));
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_display_raw() {
+    let v4 = SocketAddr::inet(([127, 0, 0, 1], 8080));
+    assert_eq!(v4.display_raw().to_string(), "127.0.0.1:8080");
+    assert_eq!(v4.display_raw().to_string(), v4.to_string());
+    let v6 = SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 443));
+    assert_eq!(v6.display_raw().to_string(), "[::1]:443");
+
+    #[cfg(unix)]
+    {
+        let u = SocketAddr::unix("/run/app.sock");
+        assert_eq!(u.to_string(), "unix:/run/app.sock");
+        assert_eq!(u.display_raw().to_string(), "/run/app.sock");
+        assert_eq!(format!("{}", SocketAddr::unix("rel.sock").display_raw()), "rel.sock");
+        assert_eq!(SocketAddr::UnnamedUnix.display_raw().to_string(), "");
+    }
+    #[cfg(target_os = "linux")]
+    assert_eq!(SocketAddr::AbstractUnix(b"app".to_vec()).display_raw().to_string(), "@app");
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_in_cidr() {
+    let v4 = SocketAddr::inet(([10, 1, 2, 3], 80));
+    assert!(v4.in_cidr("10.0.0.0/8"));
+    assert!(v4.in_cidr("10.1.2.3/32"));
+    assert!(v4.in_cidr("0.0.0.0/0"));
+    assert!(!v4.in_cidr("10.1.2.0/31"));
+    assert!(!v4.in_cidr("192.168.0.0/16"));
+    assert!(!v4.in_cidr("::/0"));
+    assert!(SocketAddr::inet((net::Ipv4Addr::new(10, 1, 2, 3).to_ipv6_mapped(), 80)).in_cidr("10.0.0.0/8"));
+
+    let v6 = SocketAddr::inet(("fd12:3456::1".parse::<net::Ipv6Addr>().unwrap(), 443));
+    assert!(v6.in_cidr("fd00::/8"));
+    assert!(v6.in_cidr("::/0"));
+    assert!(!v6.in_cidr("fe80::/10"));
+    assert!(!v6.in_cidr("0.0.0.0/0"));
+
+    for bad in ["10.0.0.0", "10.0.0.0/33", "10.0.0.0/x", "example.com/8", "fd00::/129"] {
+        assert!(!v4.in_cidr(bad) && !v6.in_cidr(bad), "{}", bad);
+    }
+    #[cfg(unix)]
+    assert!(!SocketAddr::unix("/tmp/x.sock").in_cidr("0.0.0.0/0"));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(target_os = "linux", feature = "unix"))]
+fn test_bind_with_backlog() {
+    let l = Listener::bind_with(&SocketAddr::inet(([127, 0, 0, 1], 0)), BindOptions::new().backlog(17)).unwrap();
+    // For listening sockets, Linux reports the backlog in the tcpi_sacked field of TCP_INFO.
+    let info: libc::tcp_info = sys::getsockopt(l.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_INFO).unwrap();
+    assert_eq!(info.tcpi_sacked, 17);
+    let _c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    l.accept().unwrap();
+
+    // The backlog of a UNIX socket can't easily be read back, so this only checks that a
+    // listener created by hand works, also when it replaces a stale socket.
+    let path = temp_sock_path("backlog");
+    drop(unix::UnixListener::bind(&path).unwrap());
+    let l = Listener::bind_with(&SocketAddr::Unix(path.clone()), BindOptions::new().backlog(4).reuse_stale(true)).unwrap();
+    assert_eq!(l.local_addr().unwrap(), SocketAddr::Unix(path.clone()));
+    let _c = Stream::connect(&SocketAddr::Unix(path.clone())).unwrap();
+    l.accept().unwrap();
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Collects the fields of a span or event as `name=value ` pairs.
+#[cfg(all(test, feature = "tracing", feature = "tcp"))]
+struct Fields<'a>(&'a mut String);
+
+#[cfg(all(test, feature = "tracing", feature = "tcp"))]
+impl tracing::field::Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        *self.0 += &format!("{}={:?} ", field.name(), value);
+    }
+}
+
+/// A `tracing` subscriber that records the names and fields of new spans and the fields of
+/// events.
+#[cfg(all(test, feature = "tracing", feature = "tcp"))]
+#[derive(Default)]
+struct CaptureSubscriber {
+    spans: std::sync::Mutex<Vec<String>>,
+    span_fields: std::sync::Mutex<Vec<String>>,
+    span_metadata: std::sync::Mutex<Vec<&'static tracing::Metadata<'static>>>,
+    entered: std::sync::Mutex<Vec<tracing::span::Id>>,
+    events: std::sync::Mutex<Vec<String>>
+}
+
+#[cfg(all(test, feature = "tracing", feature = "tcp"))]
+impl tracing::Subscriber for CaptureSubscriber {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span.metadata().name().to_string());
+        let mut fields = String::new();
+        span.record(&mut Fields(&mut fields));
+        self.span_fields.lock().unwrap().push(fields);
+        self.span_metadata.lock().unwrap().push(span.metadata());
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        let mut span_fields = self.span_fields.lock().unwrap();
+        values.record(&mut Fields(&mut span_fields[id.into_u64() as usize - 1]));
+    }
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut fields = String::new();
+        event.record(&mut Fields(&mut fields));
+        self.events.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, id: &tracing::span::Id) {
+        self.entered.lock().unwrap().push(id.clone());
+    }
+
+    fn exit(&self, _: &tracing::span::Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> tracing_core::span::Current {
+        match self.entered.lock().unwrap().last() {
+            Some(id) => {
+                let metadata = self.span_metadata.lock().unwrap()[id.into_u64() as usize - 1];
+                tracing_core::span::Current::new(id.clone(), metadata)
+            },
+            None => tracing_core::span::Current::none(),
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(feature = "tracing")]
+fn test_tracing_accept() {
+    let sub = std::sync::Arc::new(CaptureSubscriber::default());
+    tracing::subscriber::with_default(sub.clone(), || {
+        let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+        let _c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+        l.accept().unwrap();
+    });
+    assert_eq!(*sub.spans.lock().unwrap(), ["bind", "connect", "accept"]);
+    let accept = &sub.span_fields.lock().unwrap()[2];
+    assert!(accept.starts_with("family=ipv4 local=Inet(127.0.0.1:"), "{}", accept);
+    assert!(accept.contains(" peer=Inet(127.0.0.1:"), "{}", accept);
+    assert_eq!(*sub.events.lock().unwrap(), ["message=accepted connection "]);
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_parse_many() {
+    assert_eq!(SocketAddr::parse_many("127.0.0.1:80, [::1]:81").unwrap(),
+               [SocketAddr::inet(([127, 0, 0, 1], 80)), SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 81))]);
+    #[cfg(unix)]
+    assert_eq!(SocketAddr::parse_many("127.0.0.1:80,unix:/run/app.sock").unwrap(),
+               [SocketAddr::inet(([127, 0, 0, 1], 80)), SocketAddr::unix("/run/app.sock")]);
+
+    let e = SocketAddr::parse_many("127.0.0.1:80,nope,[::1]:81").unwrap_err();
+    assert!(matches!(&e, ParseSocketAddrError::InList { index: 1, error } if matches!(**error, ParseSocketAddrError::Inet(_))));
+    assert!(e.to_string().starts_with("address #1 in list: "));
+    // A trailing comma leaves an empty element.
+    assert!(matches!(SocketAddr::parse_many("127.0.0.1:80,"), Err(ParseSocketAddrError::InList { index: 1, .. })));
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(all(unix, feature = "unix"))]
+fn test_multi_listener() {
+    let path = temp_sock_path("multi");
+    let l = MultiListener::bind(&[SocketAddr::inet(([127, 0, 0, 1], 0)), SocketAddr::Unix(path.clone())]).unwrap();
+    let tcp = l.listeners()[0].local_addr().unwrap();
+
+    let _c = Stream::connect(&SocketAddr::Unix(path.clone())).unwrap();
+    let (s, _) = l.accept().unwrap();
+    assert_eq!(s.local_addr().unwrap(), SocketAddr::Unix(path.clone()));
+    assert!(!sys::is_nonblocking(s.as_raw_fd()).unwrap());
+
+    let c = Stream::connect(&tcp).unwrap();
+    let (s, peer) = l.accept().unwrap();
+    assert_eq!(s.local_addr().unwrap(), tcp);
+    assert_eq!(peer, c.local_addr().unwrap());
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(MultiListener::new(Vec::new()).unwrap().accept().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_read_line() {
+    use std::io::Write;
+
+    let (mut a, b) = Stream::pair().unwrap();
+    let t = std::thread::spawn(move || {
+        a.write_all(b"hel").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        a.write_all(b"lo\nnext").unwrap();
+        a
+    });
+    let mut line = String::new();
+    assert_eq!(b.read_line(&mut line, Duration::from_secs(5)).unwrap(), 6);
+    assert_eq!(line, "hello\n");
+    let mut a = t.join().unwrap();
+    assert_eq!(b.read_timeout().unwrap(), None);
+
+    // Only part of the next line has arrived.
+    let mut line = String::new();
+    let e = b.read_line(&mut line, Duration::from_millis(50)).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(line, "next");
+
+    a.write_all(b" line\n").unwrap();
+    drop(a);
+    assert_eq!(b.read_line(&mut line, Duration::from_secs(5)).unwrap(), 6);
+    assert_eq!(line, "next line\n");
+    assert_eq!(b.read_line(&mut line, Duration::from_secs(5)).unwrap(), 0);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_stream_peer_addr_unix() {
+    let path = temp_sock_path("peer-addr-unix");
+    let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    let c = Stream::connect(&SocketAddr::Unix(path.clone())).unwrap();
+    let (s, _) = l.accept().unwrap();
+    let native = c.peer_addr_unix().unwrap().unwrap();
+    assert_eq!(native.as_pathname(), Some(path.as_path()));
+    assert_eq!(SocketAddr::from(native), l.local_addr().unwrap());
+    assert!(s.peer_addr_unix().unwrap().unwrap().is_unnamed());
+    std::fs::remove_file(path).unwrap();
+
+    #[cfg(feature = "tcp")]
+    {
+        let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+        assert!(Stream::connect(&l.local_addr().unwrap()).unwrap().peer_addr_unix().unwrap().is_none());
+    }
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_into_tcp() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let s = Stream::connect(&addr).unwrap();
+    assert_eq!(SocketAddr::Inet(s.into_tcp().unwrap().peer_addr().unwrap()), addr);
+    #[cfg(all(unix, feature = "unix"))]
+    {
+        let l = l.into_unix().unwrap_err();
+        let s = Stream::connect(&addr).unwrap().into_unix().unwrap_err();
+        assert_eq!(s.peer_addr().unwrap(), addr);
+        assert_eq!(SocketAddr::Inet(l.into_tcp().unwrap().local_addr().unwrap()), addr);
+    }
+    #[cfg(not(all(unix, feature = "unix")))]
+    assert_eq!(SocketAddr::Inet(l.into_tcp().unwrap().local_addr().unwrap()), addr);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_into_unix() {
+    let path = temp_sock_path("into-unix");
+    let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+    let s = Stream::connect(&SocketAddr::Unix(path.clone())).unwrap();
+    #[cfg(feature = "tcp")]
+    let s = s.into_tcp().unwrap_err();
+    assert_eq!(s.into_unix().unwrap().peer_addr().unwrap().as_pathname(), Some(path.as_path()));
+    #[cfg(feature = "tcp")]
+    let l = l.into_tcp().unwrap_err();
+    assert_eq!(l.into_unix().unwrap().local_addr().unwrap().as_pathname(), Some(path.as_path()));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_wait_readable() {
+    use std::io::{Read,Write};
+
+    let (mut a, b) = Stream::pair().unwrap();
+    assert!(!b.wait_readable(Some(Duration::from_millis(20))).unwrap());
+    assert!(a.wait_writable(Some(Duration::from_millis(20))).unwrap());
+    let t = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        a.write_all(b"x").unwrap();
+        a
+    });
+    assert!(b.wait_readable(Some(Duration::from_secs(5))).unwrap());
+    let mut buf = [0; 1];
+    assert_eq!((&b).read(&mut buf).unwrap(), 1);
+    // EOF counts as readable too.
+    drop(t.join().unwrap());
+    assert!(b.wait_readable(None).unwrap());
+}
+
+#[test]
+fn test_socket_addr_with_port() {
+    let addr = SocketAddr::inet(([10, 0, 0, 1], 8080));
+    assert_eq!(addr.with_port(8081), SocketAddr::inet(([10, 0, 0, 1], 8081)));
+    assert_eq!(addr.port(), Some(8080));
+    let v6 = net::SocketAddrV6::new(net::Ipv6Addr::LOCALHOST, 80, 0, 3);
+    assert_eq!(SocketAddr::inet(v6).with_port(81), SocketAddr::inet(net::SocketAddrV6::new(net::Ipv6Addr::LOCALHOST, 81, 0, 3)));
+    #[cfg(unix)]
+    assert_eq!(SocketAddr::unix("/run/app.sock").with_port(81), SocketAddr::unix("/run/app.sock"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_copy_bidirectional() {
+    use std::io::{Read,Write};
+
+    // client <-> (proxy_a, proxy_b) <-> server
+    let (mut client, proxy_a) = Stream::pair().unwrap();
+    let (proxy_b, mut server) = Stream::pair().unwrap();
+    let t = std::thread::spawn(move || copy_bidirectional(&proxy_a, &proxy_b).unwrap());
+
+    client.write_all(b"request").unwrap();
+    client.shutdown_write().unwrap();
+    let mut req = Vec::new();
+    server.read_to_end(&mut req).unwrap();
+    assert_eq!(req, b"request");
+
+    // The other direction still works after the client has finished sending.
+    server.write_all(b"response!").unwrap();
+    server.shutdown_write().unwrap();
+    let mut resp = Vec::new();
+    client.read_to_end(&mut resp).unwrap();
+    assert_eq!(resp, b"response!");
+    assert_eq!(t.join().unwrap(), (7, 9));
+}
+
+#[test]
+#[cfg(all(unix, feature = "tcp"))]
+fn test_copy_bidirectional_reset() {
+    use std::io::{Read,Write};
+
+    // The client resets its connection while the server is still waiting for more.
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let client = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let proxy_a = l.accept().unwrap().0;
+    let (proxy_b, mut server) = Stream::pair().unwrap();
+    let t = std::thread::spawn(move || copy_bidirectional(&proxy_a, &proxy_b));
+
+    (&client).write_all(b"req").unwrap();
+    let mut buf = [0; 3];
+    server.read_exact(&mut buf).unwrap();
+    client.set_linger(Some(Duration::from_secs(0))).unwrap();
+    drop(client);
+
+    // The server sees EOF instead of hanging, and the proxy reports the reset.
+    assert_eq!(server.read(&mut buf).unwrap(), 0);
+    assert_eq!(t.join().unwrap().unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+#[cfg(unix)]
+fn test_set_cloexec() {
+    let cloexec = |fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC != 0;
+
+    let (a, _b) = Stream::pair().unwrap();
+    assert!(cloexec(a.as_raw_fd()));
+    a.set_cloexec(false).unwrap();
+    assert!(!cloexec(a.as_raw_fd()));
+    a.set_cloexec(true).unwrap();
+    assert!(cloexec(a.as_raw_fd()));
+
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    l.set_cloexec(false).unwrap();
+    assert!(!cloexec(l.as_raw_fd()));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_send_framed() {
+    use std::io::Read;
+
+    let (a, mut b) = Stream::pair().unwrap();
+    let body = vec![b'x'; 1000];
+    a.send_framed(&(body.len() as u32).to_be_bytes(), &body).unwrap();
+    // Both buffers went out in one write, so a single read picks up the whole frame.
+    let mut buf = vec![0; 2000];
+    assert_eq!(b.read(&mut buf).unwrap(), 1004);
+    assert_eq!(&buf[..4], &[0, 0, 3, 232]);
+    assert_eq!(&buf[4..1004], &body[..]);
+
+    // Larger than the socket buffer: needs several writes while the peer reads.
+    let body = vec![7; 4 << 20];
+    let t = std::thread::spawn(move || {
+        let mut all = Vec::new();
+        b.read_to_end(&mut all).unwrap();
+        all
+    });
+    a.send_framed(b"head", &body).unwrap();
+    drop(a);
+    let all = t.join().unwrap();
+    assert_eq!(&all[..4], b"head");
+    assert!(all[4..] == body[..]);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_listener_accept_with() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let mut options = AcceptOptions::new();
+    options.nodelay(true).read_timeout(Duration::from_secs(7));
+    let _c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (s, _) = l.accept_with(&options).unwrap();
+    assert!(s.nodelay().unwrap());
+    assert_eq!(s.read_timeout().unwrap(), Some(Duration::from_secs(7)));
+
+    let _c = Stream::connect(&l.local_addr().unwrap()).unwrap();
+    let (s, _) = l.accept_with(AcceptOptions::new().nodelay(false)).unwrap();
+    assert!(!s.nodelay().unwrap());
+    assert_eq!(s.read_timeout().unwrap(), None);
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_scope_id() {
+    let addr = "[fe80::1%2]:80".parse::<SocketAddr>().unwrap();
+    assert_eq!(addr, SocketAddr::inet(net::SocketAddrV6::new("fe80::1".parse().unwrap(), 80, 0, 2)));
+    assert_eq!(addr.to_string(), "[fe80::1%2]:80");
+    assert_eq!(addr.to_string().parse::<SocketAddr>().unwrap(), addr);
+
+    #[cfg(target_os = "linux")]
+    {
+        let lo = sys::if_nametoindex("lo").unwrap();
+        let addr = "[fe80::1%lo]:80".parse::<SocketAddr>().unwrap();
+        assert_eq!(addr, SocketAddr::inet(net::SocketAddrV6::new("fe80::1".parse().unwrap(), 80, 0, lo)));
+        assert_eq!(addr.to_string().parse::<SocketAddr>().unwrap(), addr);
+    }
+    #[cfg(unix)]
+    assert!(matches!("[fe80::1%no-such-if0]:80".parse::<SocketAddr>(), Err(ParseSocketAddrError::Inet(_))));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_set_timeout_zero() {
+    let (a, _b) = Stream::pair().unwrap();
+    let e = a.set_read_timeout(Some(Duration::ZERO)).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert!(e.to_string().contains("nonzero"));
+    assert_eq!(a.set_write_timeout(Some(Duration::ZERO)).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    a.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    assert_eq!(a.read_timeout().unwrap(), Some(Duration::from_secs(2)));
+    a.set_read_timeout(None).unwrap();
+    assert_eq!(a.read_timeout().unwrap(), None);
+}
+
+#[test]
+#[cfg(feature = "parsing")]
+fn test_socket_addr_well_known() {
+    assert_eq!(SocketAddr::loopback_v4(80).to_string(), "127.0.0.1:80");
+    assert_eq!(SocketAddr::loopback_v6(80).to_string(), "[::1]:80");
+    assert_eq!(SocketAddr::unspecified_v4(0).to_string(), "0.0.0.0:0");
+    assert_eq!(SocketAddr::unspecified_v6(443).to_string(), "[::]:443");
+    assert_eq!(SocketAddr::loopback_v4(8080), SocketAddr::inet(([127, 0, 0, 1], 8080)));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_stream_read_into_spare() {
+    use std::io::Write;
+
+    let (mut a, b) = Stream::pair().unwrap();
+    a.write_all(b"spare").unwrap();
+    let mut buf = b"> ".to_vec();
+    assert_eq!(b.read_into_spare(&mut buf, 64 * 1024).unwrap(), 5);
+    assert_eq!(buf, b"> spare");
+    assert!(buf.capacity() >= 2 + 64 * 1024);
+
+    drop(a);
+    assert_eq!(b.read_into_spare(&mut buf, 16).unwrap(), 0);
+    assert_eq!(buf, b"> spare");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_listener_accept_nonblocking() {
+    fn check(l: &Listener, c: &Stream) {
+        let (s, peer) = l.accept_nonblocking().unwrap();
+        assert!(sys::is_nonblocking(s.as_raw_fd()).unwrap());
+        let fd_flags = unsafe { libc::fcntl(s.as_raw_fd(), libc::F_GETFD) };
+        assert_ne!(fd_flags & libc::FD_CLOEXEC, 0);
+        assert_eq!(io::Read::read(&mut &s, &mut [0; 4]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(peer, c.local_addr().unwrap());
+        assert_eq!(s.peer_addr().unwrap(), peer);
+    }
+
+    #[cfg(feature = "tcp")]
+    {
+        let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+        check(&l, &Stream::connect(&l.local_addr().unwrap()).unwrap());
+        if let Ok(l) = Listener::bind(&SocketAddr::inet((net::Ipv6Addr::LOCALHOST, 0))) {
+            check(&l, &Stream::connect(&l.local_addr().unwrap()).unwrap());
+        }
+    }
+    #[cfg(feature = "unix")]
+    {
+        let path = temp_sock_path("accept-nonblocking");
+        let l = Listener::bind(&SocketAddr::Unix(path.clone())).unwrap();
+        check(&l, &Stream::connect(&SocketAddr::Unix(path.clone())).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+#[cfg(not(feature = "tcp"))]
+fn test_tcp_disabled() {
+    let addr = SocketAddr::inet(([127, 0, 0, 1], 80));
+    assert_eq!(Stream::connect(&addr).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(Listener::bind(&addr).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(Datagram::bind(&addr).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    let (a, b) = Stream::pair().unwrap();
+    assert!(a.local_addr().unwrap().is_unix());
+    let e = unsafe { Listener::from_raw_fd_with_addr(a.into_raw_fd(), &addr) }.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+    let e = unsafe { UnlinkingListener::from_raw_fd_with_addr(b.into_raw_fd(), &addr) }.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+#[cfg(all(unix, not(feature = "unix")))]
+fn test_unix_disabled() {
+    let addr = SocketAddr::unix("/tmp/unisocket-disabled.sock");
+    assert_eq!(Stream::connect(&addr).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(Listener::bind(&addr).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(Datagram::bind(&addr).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    let (a, b) = Stream::pair().unwrap();
+    assert!(a.local_addr().unwrap().is_inet());
+    let e = unsafe { Listener::from_raw_fd_with_addr(a.into_raw_fd(), &addr) }.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+    let e = unsafe { UnlinkingListener::from_raw_fd_with_addr(b.into_raw_fd(), &addr) }.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Unsupported);
 }
@@ -0,0 +1,115 @@
+//! Accounting of open sockets, enabled by the `metrics` feature.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize,Ordering};
+
+use crate::{Listener,SocketAddr,Stream};
+
+
+static COUNTED_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of `CountedStream`s that currently exist.
+///
+/// This is not the number of open sockets: plain `Stream`s aren't counted, as they can be taken
+/// apart into the libstd types at any time, so there is no place to notice them being closed.
+pub fn counted_stream_count() -> usize {
+    COUNTED_STREAMS.load(Ordering::Relaxed)
+}
+
+/// A `Stream` that is counted by `counted_stream_count()` while it exists.
+///
+/// Dereferences to the wrapped `Stream`. Taking the stream out with `into_inner()` stops
+/// counting it.
+#[derive(Debug)]
+pub struct CountedStream {
+    stream: Option<Stream>
+}
+
+impl CountedStream {
+    /// Wraps an existing `Stream`, counting it from now on.
+    pub fn new(stream: Stream) -> CountedStream {
+        COUNTED_STREAMS.fetch_add(1, Ordering::Relaxed);
+        CountedStream { stream: Some(stream) }
+    }
+
+    /// Same as `Stream::connect()`.
+    pub fn connect(s: &SocketAddr) -> io::Result<CountedStream> {
+        Stream::connect(s).map(CountedStream::new)
+    }
+
+    /// Same as `Stream::try_clone()`; the clone is counted separately.
+    pub fn try_clone(&self) -> io::Result<CountedStream> {
+        self.get_ref().try_clone().map(CountedStream::new)
+    }
+
+    /// Returns a reference to the wrapped `Stream`.
+    pub fn get_ref(&self) -> &Stream {
+        self.stream.as_ref().unwrap()
+    }
+
+    /// Takes out the wrapped `Stream`, which is no longer counted from then on.
+    pub fn into_inner(mut self) -> Stream {
+        COUNTED_STREAMS.fetch_sub(1, Ordering::Relaxed);
+        self.stream.take().unwrap()
+    }
+}
+
+impl Listener {
+    /// Same as `accept()`, but returns a `CountedStream`.
+    pub fn accept_counted(&self) -> io::Result<(CountedStream,SocketAddr)> {
+        self.accept().map(|(s, addr)| (CountedStream::new(s), addr))
+    }
+}
+
+impl Drop for CountedStream {
+    fn drop(&mut self) {
+        if self.stream.take().is_some() {
+            COUNTED_STREAMS.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl std::ops::Deref for CountedStream {
+    type Target = Stream;
+
+    fn deref(&self) -> &Stream {
+        self.get_ref()
+    }
+}
+
+impl std::ops::DerefMut for CountedStream {
+    fn deref_mut(&mut self) -> &mut Stream {
+        self.stream.as_mut().unwrap()
+    }
+}
+
+impl io::Read for CountedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { (**self).read(buf) }
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> { (**self).read_vectored(bufs) }
+}
+
+impl io::Write for CountedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { (**self).write(buf) }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> { (**self).write_vectored(bufs) }
+    fn flush(&mut self) -> io::Result<()> { (**self).flush() }
+}
+
+
+
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_counted_stream_count() {
+    let l = Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let base = counted_stream_count();
+    let c = CountedStream::connect(&l.local_addr().unwrap()).unwrap();
+    let (s, _) = l.accept_counted().unwrap();
+    let clone = c.try_clone().unwrap();
+    assert_eq!(counted_stream_count(), base + 3);
+
+    drop(clone);
+    drop(c);
+    assert_eq!(counted_stream_count(), base + 1);
+    let _s = s.into_inner();
+    assert_eq!(counted_stream_count(), base);
+}
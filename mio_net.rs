@@ -0,0 +1,296 @@
+//! Non-blocking counterparts of `Stream` and `Listener` for use with mio.
+
+use std::io;
+use mio::event::Source;
+use mio::net as mnet;
+use mio::{Interest,Registry,Token};
+
+use crate::{SocketAddr,Stream,Listener};
+
+
+/// Non-blocking version of `Stream`, wrapping a mio `TcpStream` or `UnixStream`.
+///
+/// As with mio's own types, `connect()` returns before the connection is established; wait for
+/// the stream to become writable before using it.
+#[derive(Debug)]
+pub enum MioStream {
+    #[cfg(feature = "tcp")]
+    Inet(mnet::TcpStream),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(mnet::UnixStream)
+}
+
+#[cfg(feature = "tcp")]
+impl From<mnet::TcpStream> for MioStream {
+    fn from(s: mnet::TcpStream) -> MioStream {
+        MioStream::Inet(s)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl From<mnet::UnixStream> for MioStream {
+    fn from(s: mnet::UnixStream) -> MioStream {
+        MioStream::Unix(s)
+    }
+}
+
+impl MioStream {
+    pub fn connect(s: &SocketAddr) -> io::Result<MioStream> {
+        match s {
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(s) => mnet::TcpStream::connect(*s).map(MioStream::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(s) => mnet::UnixStream::connect(s).map(MioStream::Unix),
+            // Connecting to these never blocks (or fails right away for a disabled family), so
+            // going through the std types is fine.
+            #[cfg(unix)]
+            _ => Self::from_std(Stream::connect(s)?)
+        }
+    }
+
+    /// Converts a `Stream` into a `MioStream`, switching it to non-blocking mode.
+    pub fn from_std(s: Stream) -> io::Result<MioStream> {
+        s.set_nonblocking(true)?;
+        Ok(match s {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => MioStream::Inet(mnet::TcpStream::from_std(s)),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => MioStream::Unix(mnet::UnixStream::from_std(s))
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.local_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.local_addr().map(|e| e.into())
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.peer_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.peer_addr().map(|e| e.into())
+        }
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.take_error(),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.take_error()
+        }
+    }
+}
+
+impl io::Read for MioStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.read(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.read(buf)
+        }
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.read_vectored(bufs),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.read_vectored(bufs)
+        }
+    }
+}
+
+impl io::Write for MioStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.write(buf),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.write(buf)
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.write_vectored(bufs),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.write_vectored(bufs)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.flush(),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.flush()
+        }
+    }
+}
+
+impl Source for MioStream {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.register(registry, token, interests),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.register(registry, token, interests)
+        }
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.reregister(registry, token, interests),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.reregister(registry, token, interests)
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioStream::Inet(s) => s.deregister(registry),
+            #[cfg(all(unix, feature = "unix"))]
+            MioStream::Unix(s) => s.deregister(registry)
+        }
+    }
+}
+
+
+
+
+/// Non-blocking version of `Listener`, wrapping a mio `TcpListener` or `UnixListener`.
+#[derive(Debug)]
+pub enum MioListener {
+    #[cfg(feature = "tcp")]
+    Inet(mnet::TcpListener),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(mnet::UnixListener)
+}
+
+#[cfg(feature = "tcp")]
+impl From<mnet::TcpListener> for MioListener {
+    fn from(s: mnet::TcpListener) -> MioListener {
+        MioListener::Inet(s)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl From<mnet::UnixListener> for MioListener {
+    fn from(s: mnet::UnixListener) -> MioListener {
+        MioListener::Unix(s)
+    }
+}
+
+impl MioListener {
+    /// Binds to the given address. Binding never blocks, so this goes through `Listener::bind()`.
+    pub fn bind(s: &SocketAddr) -> io::Result<MioListener> {
+        Self::from_std(Listener::bind(s)?)
+    }
+
+    /// Converts a `Listener` into a `MioListener`, switching it to non-blocking mode.
+    pub fn from_std(l: Listener) -> io::Result<MioListener> {
+        l.set_nonblocking(true)?;
+        Ok(match l {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => MioListener::Inet(mnet::TcpListener::from_std(l)),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => MioListener::Unix(mnet::UnixListener::from_std(l))
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioListener::Inet(l) => l.local_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            MioListener::Unix(l) => l.local_addr().map(|e| e.into())
+        }
+    }
+
+    /// Accepts a new connection, returning `WouldBlock` if none is pending.
+    pub fn accept(&self) -> io::Result<(MioStream,SocketAddr)> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioListener::Inet(l) => l.accept().map(|(s,e)| (s.into(), e.into())),
+            #[cfg(all(unix, feature = "unix"))]
+            MioListener::Unix(l) => l.accept().map(|(s,e)| (s.into(), e.into()))
+        }
+    }
+}
+
+impl Source for MioListener {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioListener::Inet(l) => l.register(registry, token, interests),
+            #[cfg(all(unix, feature = "unix"))]
+            MioListener::Unix(l) => l.register(registry, token, interests)
+        }
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioListener::Inet(l) => l.reregister(registry, token, interests),
+            #[cfg(all(unix, feature = "unix"))]
+            MioListener::Unix(l) => l.reregister(registry, token, interests)
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            MioListener::Inet(l) => l.deregister(registry),
+            #[cfg(all(unix, feature = "unix"))]
+            MioListener::Unix(l) => l.deregister(registry)
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+fn wait_readable(mut l: MioListener, addr: &SocketAddr) {
+    use std::time::Duration;
+
+    let mut poll = mio::Poll::new().unwrap();
+    let mut events = mio::Events::with_capacity(4);
+    poll.registry().register(&mut l, Token(7), Interest::READABLE).unwrap();
+    assert_eq!(l.accept().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+    let _c = MioStream::connect(addr).unwrap();
+    poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+    let ev = events.iter().next().expect("no event before the timeout");
+    assert_eq!(ev.token(), Token(7));
+    assert!(ev.is_readable());
+    l.accept().unwrap();
+    poll.registry().deregister(&mut l).unwrap();
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_mio_listener_inet() {
+    let l = MioListener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    wait_readable(l, &addr);
+}
+
+#[test]
+#[cfg(all(unix, feature = "unix"))]
+fn test_mio_listener_unix() {
+    let path = crate::temp_sock_path("mio");
+    let addr = SocketAddr::Unix(path.clone());
+    wait_readable(MioListener::bind(&addr).unwrap(), &addr);
+    std::fs::remove_file(path).unwrap();
+}
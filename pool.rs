@@ -0,0 +1,102 @@
+//! A simple pool of idle connections, keyed by address.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc,Mutex};
+
+use crate::{SocketAddr,Stream};
+
+
+/// A pool of idle `Stream`s, keyed by the address they are connected to.
+///
+/// Clones share the same pool, so it can be handed to multiple threads. Streams are returned to
+/// the pool with `put()`; the pool keeps at most `max_idle` of them for each address and drops
+/// the rest.
+#[derive(Debug,Clone)]
+pub struct ConnPool {
+    max_idle: usize,
+    health_check: bool,
+    idle: Arc<Mutex<HashMap<SocketAddr, Vec<Stream>>>>
+}
+
+impl ConnPool {
+    /// Creates an empty pool that keeps up to `max_idle` streams per address.
+    ///
+    /// With `health_check` set, idle streams with a pending socket error (see
+    /// `Stream::take_error()`) are discarded instead of handed out.
+    pub fn new(max_idle: usize, health_check: bool) -> ConnPool {
+        ConnPool { max_idle, health_check, idle: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Returns an idle stream connected to `addr`, or connects a new one if there is none.
+    pub fn get(&self, addr: &SocketAddr) -> io::Result<Stream> {
+        while let Some(s) = self.take_idle(addr) {
+            if !self.health_check || matches!(s.take_error(), Ok(None)) {
+                return Ok(s);
+            }
+        }
+        Stream::connect(addr)
+    }
+
+    /// Returns a stream to the pool for later reuse, returning `false` if it was dropped
+    /// instead because the pool already holds `max_idle` streams for `addr`.
+    pub fn put(&self, addr: SocketAddr, s: Stream) -> bool {
+        let mut idle = self.idle.lock().unwrap();
+        let streams = idle.entry(addr).or_default();
+        if streams.len() >= self.max_idle {
+            return false;
+        }
+        streams.push(s);
+        true
+    }
+
+    /// Returns the number of idle streams for `addr`.
+    pub fn idle_count(&self, addr: &SocketAddr) -> usize {
+        self.idle.lock().unwrap().get(addr).map_or(0, Vec::len)
+    }
+
+    /// Drops all idle streams.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    fn take_idle(&self, addr: &SocketAddr) -> Option<Stream> {
+        self.idle.lock().unwrap().get_mut(addr).and_then(Vec::pop)
+    }
+}
+
+
+
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_pool_reuse() {
+    let l = crate::Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let pool = ConnPool::new(2, true);
+
+    let s = pool.get(&addr).unwrap();
+    let local = s.local_addr().unwrap();
+    assert!(pool.put(addr.clone(), s));
+    assert_eq!(pool.idle_count(&addr), 1);
+
+    // A clone shares the idle set, and hands out the same connection again.
+    let s = pool.clone().get(&addr).unwrap();
+    assert_eq!(s.local_addr().unwrap(), local);
+    assert_eq!(pool.idle_count(&addr), 0);
+}
+
+#[test]
+#[cfg(feature = "tcp")]
+fn test_pool_max_idle() {
+    let l = crate::Listener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).unwrap();
+    let addr = l.local_addr().unwrap();
+    let pool = ConnPool::new(2, false);
+
+    let streams: Vec<Stream> = (0..3).map(|_| pool.get(&addr).unwrap()).collect();
+    let kept: Vec<bool> = streams.into_iter().map(|s| pool.put(addr.clone(), s)).collect();
+    assert_eq!(kept, [true, true, false]);
+    assert_eq!(pool.idle_count(&addr), 2);
+    pool.clear();
+    assert_eq!(pool.idle_count(&addr), 0);
+}
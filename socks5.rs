@@ -0,0 +1,210 @@
+//! Client side of the SOCKS5 protocol (RFC 1928 and RFC 1929), used by
+//! `Stream::connect_via_socks5()`.
+
+use std::io::{self,Read,Write};
+use std::net;
+
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASS: u8 = 2;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("SOCKS5 proxy: {}", msg))
+}
+
+/// Converts a non-zero reply code of the proxy into an error.
+fn reply_error(rep: u8) -> io::Error {
+    let (kind, msg) = match rep {
+        2 => (io::ErrorKind::PermissionDenied, "connection not allowed by ruleset"),
+        3 => (io::ErrorKind::Other, "network unreachable"),
+        4 => (io::ErrorKind::Other, "host unreachable"),
+        5 => (io::ErrorKind::ConnectionRefused, "connection refused"),
+        6 => (io::ErrorKind::TimedOut, "TTL expired"),
+        7 => (io::ErrorKind::Unsupported, "command not supported"),
+        8 => (io::ErrorKind::Unsupported, "address type not supported"),
+        _ => (io::ErrorKind::Other, "general failure"),
+    };
+    io::Error::new(kind, format!("SOCKS5 proxy: {}", msg))
+}
+
+fn authenticate(s: &mut net::TcpStream, user: &str, pass: &str) -> io::Result<()> {
+    if user.len() > 255 || pass.len() > 255 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "SOCKS5 username or password is too long"));
+    }
+    let mut req = vec![1, user.len() as u8];
+    req.extend_from_slice(user.as_bytes());
+    req.push(pass.len() as u8);
+    req.extend_from_slice(pass.as_bytes());
+    s.write_all(&req)?;
+
+    let mut reply = [0; 2];
+    s.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "SOCKS5 proxy: authentication failed"));
+    }
+    Ok(())
+}
+
+/// Performs the handshake on a stream connected to the proxy, asking it to connect to `target`.
+pub fn handshake(s: &mut net::TcpStream, target: &net::SocketAddr, auth: Option<(&str, &str)>) -> io::Result<()> {
+    if auth.is_some() {
+        s.write_all(&[VERSION, 2, NO_AUTH, USER_PASS])?;
+    } else {
+        s.write_all(&[VERSION, 1, NO_AUTH])?;
+    }
+    let mut reply = [0; 2];
+    s.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("unexpected protocol version"));
+    }
+    match (reply[1], auth) {
+        (NO_AUTH, _) => (),
+        (USER_PASS, Some((user, pass))) => authenticate(s, user, pass)?,
+        (NO_ACCEPTABLE_METHOD, _) => {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                      "SOCKS5 proxy: no acceptable authentication method"));
+        },
+        _ => return Err(invalid_data("unexpected authentication method")),
+    }
+
+    let mut req = vec![VERSION, CONNECT, 0];
+    match target {
+        net::SocketAddr::V4(a) => {
+            req.push(ATYP_IPV4);
+            req.extend_from_slice(&a.ip().octets());
+        },
+        net::SocketAddr::V6(a) => {
+            req.push(ATYP_IPV6);
+            req.extend_from_slice(&a.ip().octets());
+        },
+    }
+    req.extend_from_slice(&target.port().to_be_bytes());
+    s.write_all(&req)?;
+
+    let mut reply = [0; 4];
+    s.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("unexpected protocol version"));
+    }
+    if reply[1] != 0 {
+        return Err(reply_error(reply[1]));
+    }
+    // Skip the address the proxy bound for the connection, followed by its port.
+    let len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            s.read_exact(&mut len)?;
+            len[0] as usize
+        },
+        _ => return Err(invalid_data("unknown address type")),
+    };
+    let mut bound = vec![0; len + 2];
+    s.read_exact(&mut bound)
+}
+
+
+
+
+/// Runs the server side of a minimal SOCKS5 proxy for a single client, answering the connect
+/// request with `rep` and returning the requested target address.
+#[cfg(test)]
+fn serve_one(l: &net::TcpListener, auth: Option<(&str, &str)>, rep: u8) -> (net::TcpStream, net::SocketAddr) {
+    let (mut s, _) = l.accept().unwrap();
+    let mut head = [0; 2];
+    s.read_exact(&mut head).unwrap();
+    assert_eq!(head[0], VERSION);
+    let mut methods = vec![0; head[1] as usize];
+    s.read_exact(&mut methods).unwrap();
+    match auth {
+        None => s.write_all(&[VERSION, NO_AUTH]).unwrap(),
+        Some((user, pass)) => {
+            assert!(methods.contains(&USER_PASS));
+            s.write_all(&[VERSION, USER_PASS]).unwrap();
+            let mut buf = [0; 2];
+            s.read_exact(&mut buf).unwrap();
+            let mut u = vec![0; buf[1] as usize];
+            s.read_exact(&mut u).unwrap();
+            s.read_exact(&mut buf[..1]).unwrap();
+            let mut p = vec![0; buf[0] as usize];
+            s.read_exact(&mut p).unwrap();
+            let ok = u == user.as_bytes() && p == pass.as_bytes();
+            s.write_all(&[1, if ok { 0 } else { 1 }]).unwrap();
+            if !ok {
+                return (s, "0.0.0.0:0".parse().unwrap());
+            }
+        },
+    }
+
+    let mut req = [0; 4];
+    s.read_exact(&mut req).unwrap();
+    assert_eq!(&req[..3], &[VERSION, CONNECT, 0]);
+    let target = match req[3] {
+        ATYP_IPV4 => {
+            let mut buf = [0; 6];
+            s.read_exact(&mut buf).unwrap();
+            net::SocketAddr::from(([buf[0], buf[1], buf[2], buf[3]], u16::from_be_bytes([buf[4], buf[5]])))
+        },
+        ATYP_IPV6 => {
+            let mut buf = [0; 18];
+            s.read_exact(&mut buf).unwrap();
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&buf[..16]);
+            net::SocketAddr::from((ip, u16::from_be_bytes([buf[16], buf[17]])))
+        },
+        t => panic!("unexpected address type {}", t),
+    };
+    s.write_all(&[VERSION, rep, 0, ATYP_DOMAIN, 5, b'p', b'r', b'o', b'x', b'y', 0, 0]).unwrap();
+    (s, target)
+}
+
+#[test]
+fn test_socks5_connect() {
+    use crate::{SocketAddr,Stream};
+
+    let proxy = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    let target: net::SocketAddr = "10.1.2.3:4567".parse().unwrap();
+    let t = std::thread::spawn(move || {
+        let (mut s, requested) = serve_one(&proxy, None, 0);
+        assert_eq!(requested, target);
+        s.write_all(b"hello").unwrap();
+
+        let (mut s, requested) = serve_one(&proxy, Some(("user", "secret")), 0);
+        assert_eq!(requested, "[2001:db8::1]:80".parse().unwrap());
+        s.write_all(b"authed").unwrap();
+
+        serve_one(&proxy, Some(("user", "secret")), 0);
+        serve_one(&proxy, None, 5);
+    });
+
+    let mut s = Stream::connect_via_socks5(&SocketAddr::Inet(target), proxy_addr, None).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), SocketAddr::Inet(proxy_addr));
+    let mut buf = [0; 5];
+    s.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    let auth = Some(("user".to_string(), "secret".to_string()));
+    let mut s = Stream::connect_via_socks5(&SocketAddr::inet(([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1], 80)), proxy_addr, auth).unwrap();
+    let mut buf = [0; 6];
+    s.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"authed");
+
+    let auth = Some(("user".to_string(), "wrong".to_string()));
+    let e = Stream::connect_via_socks5(&SocketAddr::Inet(target), proxy_addr, auth).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = Stream::connect_via_socks5(&SocketAddr::Inet(target), proxy_addr, None).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    t.join().unwrap();
+
+    #[cfg(all(unix, feature = "unix"))]
+    assert_eq!(Stream::connect_via_socks5(&SocketAddr::unix("/tmp/x.sock"), proxy_addr, None).unwrap_err().kind(),
+               io::ErrorKind::Unsupported);
+}
@@ -0,0 +1,555 @@
+//! Thin wrappers around the raw socket syscalls that libstd doesn't expose.
+
+use std::io;
+use std::mem;
+#[cfg(feature = "tcp")]
+use std::net;
+use std::os::unix::io::RawFd;
+#[cfg(any(feature = "tcp", feature = "unix"))]
+use std::os::unix::io::{AsRawFd,FromRawFd};
+use std::time::Duration;
+
+#[cfg(feature = "tcp")]
+use crate::BindOptions;
+#[cfg(any(feature = "unix", target_os = "linux", target_os = "android"))]
+use crate::SocketAddr;
+
+/// Converts a libc return value into an `io::Result`, picking up `errno` on failure.
+fn cvt<T: PartialOrd + Default>(r: T) -> io::Result<T> {
+    if r < T::default() {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+pub fn recv(fd: RawFd, buf: &mut [u8], flags: libc::c_int) -> io::Result<usize> {
+    let r = cvt(unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), flags) })?;
+    Ok(r as usize)
+}
+
+/// Same as `recv()`, but into a buffer that may be uninitialized. On success, the first bytes of
+/// `buf` up to the returned count are initialized.
+pub fn recv_uninit(fd: RawFd, buf: &mut [mem::MaybeUninit<u8>], flags: libc::c_int) -> io::Result<usize> {
+    let r = cvt(unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), flags) })?;
+    Ok(r as usize)
+}
+
+pub fn send(fd: RawFd, buf: &[u8], flags: libc::c_int) -> io::Result<usize> {
+    let r = cvt(unsafe { libc::send(fd, buf.as_ptr() as *const libc::c_void, buf.len(), flags) })?;
+    Ok(r as usize)
+}
+
+/// Closes `fd`, which must not be used afterwards even if an error is returned.
+pub fn close(fd: RawFd) -> io::Result<()> {
+    cvt(unsafe { libc::close(fd) }).map(|_| ())
+}
+
+pub fn getsockopt<T: Copy>(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<T> {
+    unsafe {
+        let mut val: T = mem::zeroed();
+        let mut len = mem::size_of::<T>() as libc::socklen_t;
+        cvt(libc::getsockopt(fd, level, name, &mut val as *mut T as *mut libc::c_void, &mut len))?;
+        Ok(val)
+    }
+}
+
+pub fn setsockopt<T: Copy>(fd: RawFd, level: libc::c_int, name: libc::c_int, val: T) -> io::Result<()> {
+    let len = mem::size_of::<T>() as libc::socklen_t;
+    cvt(unsafe { libc::setsockopt(fd, level, name, &val as *const T as *const libc::c_void, len) })?;
+    Ok(())
+}
+
+/// Returns the number of bytes that can be read without blocking, using `FIONREAD`.
+pub fn bytes_available(fd: RawFd) -> io::Result<usize> {
+    let mut n: libc::c_int = 0;
+    cvt(unsafe { libc::ioctl(fd, libc::FIONREAD, &mut n) })?;
+    Ok(n as usize)
+}
+
+/// Waits until `fd` is readable or `timeout` passes, returning whether it became readable.
+pub fn poll_readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+    poll(fd, libc::POLLIN, Some(timeout))
+}
+
+/// Waits until one of `events` is reported for `fd` (or an error or hangup is), or `timeout`
+/// passes. Returns whether anything was reported; without a timeout, waits forever.
+pub fn poll(fd: RawFd, events: libc::c_short, timeout: Option<Duration>) -> io::Result<bool> {
+    let deadline = timeout.map(|t| std::time::Instant::now() + t);
+    loop {
+        let ms = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                // Round up, so we never wake up just before the deadline and report a timeout early.
+                remaining.as_nanos().div_ceil(1_000_000).min(libc::c_int::MAX as u128) as libc::c_int
+            },
+            None => -1,
+        };
+        let mut pfd = libc::pollfd { fd, events, revents: 0 };
+        match cvt(unsafe { libc::poll(&mut pfd, 1, ms) }) {
+            Ok(n) => return Ok(n > 0),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Waits until at least one of `fds` is readable, returning the indices of those that are.
+pub fn poll_readable_any(fds: &[RawFd]) -> io::Result<Vec<usize>> {
+    let mut pfds: Vec<_> = fds.iter().map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 }).collect();
+    loop {
+        match cvt(unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, -1) }) {
+            Ok(_) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(pfds.iter().enumerate().filter(|(_, p)| p.revents != 0).map(|(i, _)| i).collect())
+}
+
+/// Sets or clears `FD_CLOEXEC` on `fd`.
+pub fn set_cloexec(fd: RawFd, cloexec: bool) -> io::Result<()> {
+    let flags = cvt(unsafe { libc::fcntl(fd, libc::F_GETFD) })?;
+    let new = if cloexec { flags | libc::FD_CLOEXEC } else { flags & !libc::FD_CLOEXEC };
+    if new != flags {
+        cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, new) })?;
+    }
+    Ok(())
+}
+
+/// Returns the index of the network interface called `name`, or `None` if there is none.
+#[cfg(feature = "parsing")]
+pub fn if_nametoindex(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        i => Some(i),
+    }
+}
+
+/// Returns whether `O_NONBLOCK` is set on `fd`.
+pub fn is_nonblocking(fd: RawFd) -> io::Result<bool> {
+    let flags = cvt(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+    Ok(flags & libc::O_NONBLOCK != 0)
+}
+
+/// Returns the level and name of the option holding the traffic class byte for an IPv4 or IPv6
+/// socket.
+#[cfg(feature = "tcp")]
+fn tos_option(v6: bool) -> (libc::c_int, libc::c_int) {
+    if v6 { (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) } else { (libc::IPPROTO_IP, libc::IP_TOS) }
+}
+
+#[cfg(feature = "tcp")]
+pub fn set_tos(fd: RawFd, v6: bool, tos: u32) -> io::Result<()> {
+    let (level, name) = tos_option(v6);
+    setsockopt(fd, level, name, tos as libc::c_int)
+}
+
+#[cfg(feature = "tcp")]
+pub fn tos(fd: RawFd, v6: bool) -> io::Result<u32> {
+    let (level, name) = tos_option(v6);
+    getsockopt::<libc::c_int>(fd, level, name).map(|v| v as u32)
+}
+
+pub fn setsockopt_raw(fd: RawFd, level: libc::c_int, name: libc::c_int, val: &[u8]) -> io::Result<()> {
+    cvt(unsafe { libc::setsockopt(fd, level, name, val.as_ptr() as *const libc::c_void, val.len() as libc::socklen_t) })?;
+    Ok(())
+}
+
+pub fn getsockopt_raw(fd: RawFd, level: libc::c_int, name: libc::c_int, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = buf.len() as libc::socklen_t;
+    cvt(unsafe { libc::getsockopt(fd, level, name, buf.as_mut_ptr() as *mut libc::c_void, &mut len) })?;
+    Ok(len as usize)
+}
+
+#[cfg(feature = "tcp")]
+pub fn set_keepalive(fd: RawFd, keepalive: Option<Duration>) -> io::Result<()> {
+    setsockopt::<libc::c_int>(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, keepalive.is_some() as libc::c_int)?;
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "netbsd", target_os = "dragonfly"))]
+    let idle = libc::TCP_KEEPIDLE;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let idle = libc::TCP_KEEPALIVE;
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "netbsd", target_os = "dragonfly", target_os = "macos", target_os = "ios"))]
+    {
+        if let Some(d) = keepalive {
+            let secs = d.as_secs().clamp(1, libc::c_int::MAX as u64) as libc::c_int;
+            setsockopt(fd, libc::IPPROTO_TCP, idle, secs)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn set_buffer_size(fd: RawFd, name: libc::c_int, size: usize) -> io::Result<()> {
+    setsockopt(fd, libc::SOL_SOCKET, name, size.min(libc::c_int::MAX as usize) as libc::c_int)
+}
+
+pub fn buffer_size(fd: RawFd, name: libc::c_int) -> io::Result<usize> {
+    getsockopt::<libc::c_int>(fd, libc::SOL_SOCKET, name).map(|v| v as usize)
+}
+
+// Apple platforms measure SO_LINGER in clock ticks, SO_LINGER_SEC is the one in seconds.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const SO_LINGER: libc::c_int = libc::SO_LINGER_SEC;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const SO_LINGER: libc::c_int = libc::SO_LINGER;
+
+pub fn set_linger(fd: RawFd, linger: Option<Duration>) -> io::Result<()> {
+    let val = libc::linger {
+        l_onoff: linger.is_some() as libc::c_int,
+        l_linger: linger.map_or(0, |d| d.as_secs().min(libc::c_int::MAX as u64) as libc::c_int),
+    };
+    setsockopt(fd, libc::SOL_SOCKET, SO_LINGER, val)
+}
+
+pub fn linger(fd: RawFd) -> io::Result<Option<Duration>> {
+    let val: libc::linger = getsockopt(fd, libc::SOL_SOCKET, SO_LINGER)?;
+    Ok(if val.l_onoff != 0 { Some(Duration::from_secs(val.l_linger as u64)) } else { None })
+}
+
+/// Converts a `std::net::SocketAddr` into its C representation.
+#[cfg(feature = "tcp")]
+fn sockaddr(addr: &net::SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    unsafe {
+        let mut storage: libc::sockaddr_storage = mem::zeroed();
+        let len = match addr {
+            net::SocketAddr::V4(a) => {
+                let sin = &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in);
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = a.port().to_be();
+                sin.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(a.ip().octets()) };
+                mem::size_of::<libc::sockaddr_in>()
+            },
+            net::SocketAddr::V6(a) => {
+                let sin6 = &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6);
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = a.port().to_be();
+                sin6.sin6_addr = libc::in6_addr { s6_addr: a.ip().octets() };
+                sin6.sin6_flowinfo = a.flowinfo();
+                sin6.sin6_scope_id = a.scope_id();
+                mem::size_of::<libc::sockaddr_in6>()
+            },
+        };
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd",
+                  target_os = "netbsd", target_os = "dragonfly"))]
+        {
+            storage.ss_len = len as u8;
+        }
+        (storage, len as libc::socklen_t)
+    }
+}
+
+/// Creates a close-on-exec socket of the given family and type.
+#[cfg(any(feature = "tcp", feature = "unix"))]
+fn socket(family: libc::c_int, ty: libc::c_int) -> io::Result<RawFd> {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+    {
+        cvt(unsafe { libc::socket(family, ty | libc::SOCK_CLOEXEC, 0) })
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                  target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+    {
+        let fd = cvt(unsafe { libc::socket(family, ty, 0) })?;
+        if let Err(e) = cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) }) {
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        Ok(fd)
+    }
+}
+
+/// Creates a TCP listener by hand, so that options can be set before `bind()`.
+#[cfg(feature = "tcp")]
+pub fn tcp_listener(addr: &net::SocketAddr, opts: &BindOptions) -> io::Result<net::TcpListener> {
+    let family = if addr.is_ipv4() { libc::AF_INET } else { libc::AF_INET6 };
+    // Wrap the fd right away so it gets closed on any error below.
+    let l = unsafe { net::TcpListener::from_raw_fd(socket(family, libc::SOCK_STREAM)?) };
+    let fd = l.as_raw_fd();
+
+    // libstd always sets SO_REUSEADDR on UNIX, do the same here.
+    setsockopt::<libc::c_int>(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+    if opts.reuse_port {
+        setsockopt::<libc::c_int>(fd, libc::SOL_SOCKET, libc::SO_REUSEPORT, 1)?;
+    }
+    if let (Some(v6_only), true) = (opts.v6_only, addr.is_ipv6()) {
+        setsockopt::<libc::c_int>(fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, v6_only as libc::c_int)?;
+    }
+
+    let (storage, len) = sockaddr(addr);
+    cvt(unsafe { libc::bind(fd, &storage as *const libc::sockaddr_storage as *const libc::sockaddr, len) })?;
+    listen(fd, opts.backlog.unwrap_or(128))?;
+    Ok(l)
+}
+
+/// Converts a UNIX socket path, or an abstract name on Linux, into its C representation.
+#[cfg(feature = "unix")]
+fn sockaddr_un(addr: &SocketAddr) -> io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut sun: libc::sockaddr_un = unsafe { mem::zeroed() };
+    sun.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let (bytes, nul) = match addr {
+        SocketAddr::Unix(p) => {
+            let bytes = p.as_os_str().as_bytes();
+            if bytes.contains(&0) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "paths must not contain interior null bytes"));
+            }
+            (bytes.to_vec(), 1)
+        },
+        #[cfg(target_os = "linux")]
+        SocketAddr::AbstractUnix(n) => ([&[0][..], n].concat(), 0),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a named UNIX socket address")),
+    };
+    if bytes.len() + nul > sun.sun_path.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "path must be shorter than SUN_LEN"));
+    }
+    for (dst, &src) in sun.sun_path.iter_mut().zip(&bytes) {
+        *dst = src as libc::c_char;
+    }
+    let len = mem::size_of::<libc::sa_family_t>() + bytes.len() + nul;
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd",
+              target_os = "netbsd", target_os = "dragonfly"))]
+    {
+        sun.sun_len = len as u8;
+    }
+    Ok((sun, len as libc::socklen_t))
+}
+
+/// Creates a UNIX listener by hand, so that `listen()` is called once with the given backlog.
+#[cfg(feature = "unix")]
+pub fn unix_listener(addr: &SocketAddr, backlog: u32) -> io::Result<std::os::unix::net::UnixListener> {
+    let (sun, len) = sockaddr_un(addr)?;
+    // Wrap the fd right away so it gets closed on any error below.
+    let l = unsafe { std::os::unix::net::UnixListener::from_raw_fd(socket(libc::AF_UNIX, libc::SOCK_STREAM)?) };
+    let fd = l.as_raw_fd();
+    cvt(unsafe { libc::bind(fd, &sun as *const libc::sockaddr_un as *const libc::sockaddr, len) })?;
+    listen(fd, backlog)?;
+    Ok(l)
+}
+
+/// Starts listening on `fd`, or updates the backlog of a socket that is already listening.
+pub fn listen(fd: RawFd, backlog: u32) -> io::Result<()> {
+    cvt(unsafe { libc::listen(fd, backlog.min(libc::c_int::MAX as u32) as libc::c_int) }).map(|_| ())
+}
+
+/// Accepts a connection with `accept4()`, creating the new socket non-blocking and close-on-exec
+/// in one go, and returns it along with the peer address.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn accept_nonblocking(fd: RawFd) -> io::Result<(std::os::unix::io::OwnedFd, SocketAddr)> {
+    use std::os::unix::io::{FromRawFd,OwnedFd};
+
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let s = loop {
+        let r = unsafe {
+            libc::accept4(fd, &mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr, &mut len,
+                          libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC)
+        };
+        match cvt(r) {
+            Ok(s) => break unsafe { OwnedFd::from_raw_fd(s) },
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    };
+    let addr = match storage.ss_family as libc::c_int {
+        #[cfg(feature = "tcp")]
+        libc::AF_INET => {
+            let sin = unsafe { &*(&storage as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+            let ip = net::Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes());
+            SocketAddr::Inet(net::SocketAddrV4::new(ip, u16::from_be(sin.sin_port)).into())
+        },
+        #[cfg(feature = "tcp")]
+        libc::AF_INET6 => {
+            let sin6 = unsafe { &*(&storage as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+            let ip = net::Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+            SocketAddr::Inet(net::SocketAddrV6::new(ip, u16::from_be(sin6.sin6_port), sin6.sin6_flowinfo,
+                                                    sin6.sin6_scope_id).into())
+        },
+        #[cfg(feature = "unix")]
+        libc::AF_UNIX => {
+            let sun = unsafe { &*(&storage as *const libc::sockaddr_storage as *const libc::sockaddr_un) };
+            unix_sockaddr(sun, len)
+        },
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "accepted a socket of an unknown address family")),
+    };
+    Ok((s, addr))
+}
+
+// Apple platforms rely on SO_NOSIGPIPE instead, which `send_with_fds()` sets.
+#[cfg(all(feature = "unix", not(target_vendor = "apple")))]
+const MSG_NOSIGNAL: libc::c_int = libc::MSG_NOSIGNAL;
+#[cfg(all(feature = "unix", target_vendor = "apple"))]
+const MSG_NOSIGNAL: libc::c_int = 0;
+
+#[cfg(all(feature = "unix",
+          any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+const MSG_CMSG_CLOEXEC: libc::c_int = libc::MSG_CMSG_CLOEXEC;
+#[cfg(all(feature = "unix",
+          not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                  target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))))]
+const MSG_CMSG_CLOEXEC: libc::c_int = 0;
+
+/// Allocates a suitably aligned buffer for control messages carrying `nfds` file descriptors.
+#[cfg(feature = "unix")]
+fn cmsg_buffer(nfds: usize) -> Vec<u64> {
+    let space = unsafe { libc::CMSG_SPACE((nfds * mem::size_of::<RawFd>()) as u32) } as usize;
+    vec![0u64; space.div_ceil(8)]
+}
+
+/// Sends `buf` along with the given file descriptors as `SCM_RIGHTS` ancillary data, without
+/// raising `SIGPIPE` if the peer has gone away.
+#[cfg(feature = "unix")]
+pub fn send_with_fds(fd: RawFd, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+    #[cfg(target_vendor = "apple")]
+    setsockopt(fd, libc::SOL_SOCKET, libc::SO_NOSIGPIPE, 1 as libc::c_int)?;
+    let mut iov = libc::iovec { iov_base: buf.as_ptr() as *mut libc::c_void, iov_len: buf.len() };
+    let mut control = cmsg_buffer(fds.len());
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            let len = mem::size_of_val(fds);
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = libc::CMSG_SPACE(len as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(len as u32) as _;
+            std::ptr::copy_nonoverlapping(fds.as_ptr() as *const u8, libc::CMSG_DATA(cmsg), len);
+        }
+        cvt(libc::sendmsg(fd, &msg, MSG_NOSIGNAL)).map(|n| n as usize)
+    }
+}
+
+/// Receives into `buf`, storing any file descriptors passed as `SCM_RIGHTS` ancillary data in
+/// `fds`. Returns the number of bytes and file descriptors received.
+///
+/// If more descriptors were sent than fit into `fds`, all received ones are closed and an error
+/// is returned, as the kernel has already discarded the rest.
+#[cfg(feature = "unix")]
+pub fn recv_with_fds(fd: RawFd, buf: &mut [u8], fds: &mut [RawFd]) -> io::Result<(usize, usize)> {
+    let mut iov = libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void, iov_len: buf.len() };
+    let mut control = cmsg_buffer(fds.len());
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = (control.len() * 8) as _;
+        }
+        let n = cvt(libc::recvmsg(fd, &mut msg, MSG_CMSG_CLOEXEC))? as usize;
+
+        let mut nfds = 0;
+        let mut truncated = msg.msg_flags & libc::MSG_CTRUNC != 0;
+        let mut cmsg = if msg.msg_controllen > 0 { libc::CMSG_FIRSTHDR(&msg) } else { std::ptr::null_mut() };
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / mem::size_of::<RawFd>();
+                for i in 0..count {
+                    let received = std::ptr::read_unaligned(data.add(i));
+                    if nfds < fds.len() {
+                        fds[nfds] = received;
+                        nfds += 1;
+                    } else {
+                        // The control buffer is rounded up and may hold a few more than asked for.
+                        libc::close(received);
+                        truncated = true;
+                    }
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        if truncated {
+            for &received in &fds[..nfds] {
+                libc::close(received);
+            }
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "received more file descriptors than fit into the buffer"));
+        }
+        Ok((n, nfds))
+    }
+}
+
+/// Converts an `AF_UNIX` address filled in by the kernel into a `SocketAddr`.
+#[cfg(all(feature = "unix", any(target_os = "linux", target_os = "android")))]
+fn unix_sockaddr(addr: &libc::sockaddr_un, len: libc::socklen_t) -> SocketAddr {
+    use std::os::unix::ffi::OsStrExt;
+
+    let offset = mem::size_of::<libc::sa_family_t>();
+    let len = (len as usize).saturating_sub(offset).min(addr.sun_path.len());
+    let path: Vec<u8> = addr.sun_path[..len].iter().map(|&c| c as u8).collect();
+    match path.first() {
+        None => SocketAddr::UnnamedUnix,
+        #[cfg(target_os = "linux")]
+        Some(0) => SocketAddr::AbstractUnix(path[1..].to_vec()),
+        _ => {
+            let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+            SocketAddr::Unix(std::ffi::OsStr::from_bytes(&path[..end]).into())
+        }
+    }
+}
+
+/// Receives a datagram into `buf` along with its source address and the (uid, gid, pid) passed
+/// as `SCM_CREDENTIALS` ancillary data, which requires `SO_PASSCRED` to be set.
+#[cfg(all(feature = "unix", any(target_os = "linux", target_os = "android")))]
+pub fn recv_with_cred(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, (u32, u32, i32))> {
+    let mut iov = libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void, iov_len: buf.len() };
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<libc::ucred>() as u32) } as usize;
+    let mut control = vec![0u64; space.div_ceil(8)];
+    unsafe {
+        let mut addr: libc::sockaddr_un = mem::zeroed();
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = (control.len() * 8) as _;
+        let n = cvt(libc::recvmsg(fd, &mut msg, MSG_CMSG_CLOEXEC))? as usize;
+
+        let mut cred = None;
+        let mut cmsg = if msg.msg_controllen > 0 { libc::CMSG_FIRSTHDR(&msg) } else { std::ptr::null_mut() };
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_CREDENTIALS {
+                let c = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::ucred);
+                cred = Some((c.uid, c.gid, c.pid));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        let cred = cred.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no credentials received"))?;
+        Ok((n, unix_sockaddr(&addr, msg.msg_namelen), cred))
+    }
+}
+
+/// Returns the (uid, gid, pid) of the process on the other end of a UNIX socket.
+#[cfg(all(feature = "unix", any(target_os = "linux", target_os = "android")))]
+pub fn peer_cred(fd: RawFd) -> io::Result<(u32, u32, Option<i32>)> {
+    let c: libc::ucred = getsockopt(fd, libc::SOL_SOCKET, libc::SO_PEERCRED)?;
+    Ok((c.uid, c.gid, Some(c.pid)))
+}
+
+/// Returns the (uid, gid, pid) of the process on the other end of a UNIX socket.
+#[cfg(all(feature = "unix",
+          any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd",
+              target_os = "netbsd", target_os = "dragonfly")))]
+pub fn peer_cred(fd: RawFd) -> io::Result<(u32, u32, Option<i32>)> {
+    let mut uid = 0;
+    let mut gid = 0;
+    cvt(unsafe { libc::getpeereid(fd, &mut uid, &mut gid) })?;
+    Ok((uid, gid, None))
+}
+
+/// Returns the (uid, gid, pid) of the process on the other end of a UNIX socket.
+#[cfg(all(feature = "unix",
+          not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios",
+                  target_os = "freebsd", target_os = "openbsd", target_os = "netbsd",
+                  target_os = "dragonfly"))))]
+pub fn peer_cred(_fd: RawFd) -> io::Result<(u32, u32, Option<i32>)> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "peer credentials are not supported on this platform"))
+}
@@ -0,0 +1,242 @@
+//! Asynchronous counterparts of `Stream` and `Listener` for use with tokio.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use tokio::io::{AsyncRead,AsyncWrite,ReadBuf};
+use tokio::net as tnet;
+
+use crate::{SocketAddr,Stream,Listener};
+
+
+/// Asynchronous version of `Stream`, wrapping a tokio `TcpStream` or `UnixStream`.
+#[derive(Debug)]
+pub enum AsyncStream {
+    #[cfg(feature = "tcp")]
+    Inet(tnet::TcpStream),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(tnet::UnixStream)
+}
+
+#[cfg(feature = "tcp")]
+impl From<tnet::TcpStream> for AsyncStream {
+    fn from(s: tnet::TcpStream) -> AsyncStream {
+        AsyncStream::Inet(s)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl From<tnet::UnixStream> for AsyncStream {
+    fn from(s: tnet::UnixStream) -> AsyncStream {
+        AsyncStream::Unix(s)
+    }
+}
+
+impl AsyncStream {
+    pub async fn connect(s: &SocketAddr) -> io::Result<AsyncStream> {
+        match s {
+            #[cfg(feature = "tcp")]
+            SocketAddr::Inet(s) => tnet::TcpStream::connect(s).await.map(AsyncStream::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            SocketAddr::Unix(s) => tnet::UnixStream::connect(s).await.map(AsyncStream::Unix),
+            // Connecting to these never blocks (or fails right away for a disabled family), so
+            // going through the std types is fine.
+            #[cfg(unix)]
+            _ => Self::from_std(Stream::connect(s)?)
+        }
+    }
+
+    /// Converts a `Stream` into an `AsyncStream`, switching it to non-blocking mode.
+    ///
+    /// Like tokio's own `from_std()` methods, this must be called from within a tokio runtime.
+    pub fn from_std(s: Stream) -> io::Result<AsyncStream> {
+        s.set_nonblocking(true)?;
+        match s {
+            #[cfg(feature = "tcp")]
+            Stream::Inet(s) => tnet::TcpStream::from_std(s).map(AsyncStream::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Stream::Unix(s) => tnet::UnixStream::from_std(s).map(AsyncStream::Unix)
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => s.local_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => s.local_addr().map(|e| std::os::unix::net::SocketAddr::from(e).into())
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => s.peer_addr().map(SocketAddr::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => s.peer_addr().map(|e| std::os::unix::net::SocketAddr::from(e).into())
+        }
+    }
+}
+
+impl AsyncRead for AsyncStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => Pin::new(s).poll_read(cx, buf)
+        }
+    }
+}
+
+impl AsyncWrite for AsyncStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => Pin::new(s).poll_write(cx, buf)
+        }
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => Pin::new(s).poll_write_vectored(cx, bufs)
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => s.is_write_vectored(),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => s.is_write_vectored()
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => Pin::new(s).poll_flush(cx)
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            #[cfg(feature = "tcp")]
+            AsyncStream::Inet(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncStream::Unix(s) => Pin::new(s).poll_shutdown(cx)
+        }
+    }
+}
+
+
+
+
+/// Asynchronous version of `Listener`, wrapping a tokio `TcpListener` or `UnixListener`.
+#[derive(Debug)]
+pub enum AsyncListener {
+    #[cfg(feature = "tcp")]
+    Inet(tnet::TcpListener),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(tnet::UnixListener)
+}
+
+#[cfg(feature = "tcp")]
+impl From<tnet::TcpListener> for AsyncListener {
+    fn from(s: tnet::TcpListener) -> AsyncListener {
+        AsyncListener::Inet(s)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl From<tnet::UnixListener> for AsyncListener {
+    fn from(s: tnet::UnixListener) -> AsyncListener {
+        AsyncListener::Unix(s)
+    }
+}
+
+impl AsyncListener {
+    /// Binds to the given address. Binding never blocks, so this goes through `Listener::bind()`
+    /// and must be called from within a tokio runtime.
+    pub async fn bind(s: &SocketAddr) -> io::Result<AsyncListener> {
+        Self::from_std(Listener::bind(s)?)
+    }
+
+    /// Converts a `Listener` into an `AsyncListener`, switching it to non-blocking mode.
+    ///
+    /// Like tokio's own `from_std()` methods, this must be called from within a tokio runtime.
+    pub fn from_std(l: Listener) -> io::Result<AsyncListener> {
+        l.set_nonblocking(true)?;
+        match l {
+            #[cfg(feature = "tcp")]
+            Listener::Inet(l) => tnet::TcpListener::from_std(l).map(AsyncListener::Inet),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(l) => tnet::UnixListener::from_std(l).map(AsyncListener::Unix)
+        }
+    }
+
+    pub async fn accept(&self) -> io::Result<(AsyncStream,SocketAddr)> {
+        match self {
+            #[cfg(feature = "tcp")]
+            AsyncListener::Inet(l) => l.accept().await.map(|(s,e)| (s.into(), e.into())),
+            #[cfg(all(unix, feature = "unix"))]
+            AsyncListener::Unix(l) => l.accept().await
+                .map(|(s,e)| (s.into(), std::os::unix::net::SocketAddr::from(e).into()))
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+async fn roundtrip(l: AsyncListener, addr: &SocketAddr) {
+    use tokio::io::{AsyncReadExt,AsyncWriteExt};
+
+    let client = tokio::spawn({
+        let addr = addr.clone();
+        async move {
+            let mut c = AsyncStream::connect(&addr).await.unwrap();
+            c.write_all(b"ping").await.unwrap();
+            let mut buf = [0; 4];
+            c.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"pong");
+        }
+    });
+    let (mut s, _) = l.accept().await.unwrap();
+    let mut buf = [0; 4];
+    s.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    s.write_all(b"pong").await.unwrap();
+    client.await.unwrap();
+}
+
+#[tokio::test]
+#[cfg(feature = "tcp")]
+async fn test_async_roundtrip_inet() {
+    let l = AsyncListener::bind(&SocketAddr::inet(([127, 0, 0, 1], 0))).await.unwrap();
+    let addr = match &l {
+        #[cfg(feature = "tcp")]
+        AsyncListener::Inet(l) => SocketAddr::from(l.local_addr().unwrap()),
+        #[cfg(all(unix, feature = "unix"))]
+        AsyncListener::Unix(_) => unreachable!()
+    };
+    roundtrip(l, &addr).await;
+}
+
+#[tokio::test]
+#[cfg(all(unix, feature = "unix"))]
+async fn test_async_roundtrip_unix() {
+    let path = crate::temp_sock_path("async");
+    let addr = SocketAddr::Unix(path.clone());
+    let l = AsyncListener::bind(&addr).await.unwrap();
+    roundtrip(l, &addr).await;
+    std::fs::remove_file(path).unwrap();
+}